@@ -0,0 +1,304 @@
+use crate::limiter::{EscalationStep, LimiterMode, MemoryTrigger};
+use chrono::{DateTime, Local, NaiveDate, NaiveTime, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_MAX_BYTES: u64 = 5 * 1024 * 1024;
+pub const DEFAULT_FILES: usize = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Stop,   // Entered the duty cycle
+    Freeze, // Held stopped by global mode or a memory rule
+    Cont,
+    Renice,
+    Signal, // Escalation or memory termination
+}
+
+impl Action {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Action::Stop => "stop",
+            Action::Freeze => "freeze",
+            Action::Cont => "cont",
+            Action::Renice => "renice",
+            Action::Signal => "signal",
+        }
+    }
+}
+
+/// The measurement that led to an action.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Trigger {
+    GlobalLoad { load_percent: f32, limit_percent: u32 },
+    DutyCycle { share_percent: u32 },
+    Escalation { cpu_percent: f32, step: EscalationStep },
+    Memory { rule: MemoryTrigger },
+    Released, // No longer covered by the settings, or the limiter stopped
+    Manual,   // Paused or resumed by hand, e.g. from the tray
+}
+
+impl Trigger {
+    pub fn describe(&self) -> String {
+        match self {
+            Trigger::GlobalLoad { load_percent, limit_percent } => {
+                format!("load {:.1}% vs limit {}%", load_percent, limit_percent)
+            }
+            Trigger::DutyCycle { share_percent } => format!("duty cycle {}%", share_percent),
+            Trigger::Escalation { cpu_percent, step } => format!("escalation {:?} at {:.1}%", step, cpu_percent),
+            Trigger::Memory { rule } => format!("memory {:?}", rule),
+            Trigger::Released => "released".to_string(),
+            Trigger::Manual => "manual".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum Outcome {
+    Ok,
+    Failed { errno: String },
+}
+
+impl<T> From<&nix::Result<T>> for Outcome {
+    fn from(result: &nix::Result<T>) -> Self {
+        match result {
+            Ok(_) => Outcome::Ok,
+            Err(errno) => Outcome::Failed { errno: format!("{:?}", errno) },
+        }
+    }
+}
+
+/// One line of the audit log.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    pub time: DateTime<Local>,
+    pub action: Action,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signal: Option<String>, // For `Signal`, e.g. "SIGTERM"
+    pub pid: i32,
+    pub name: String,
+    pub user: Option<String>,
+    pub mode: LimiterMode,
+    pub trigger: Trigger,
+    #[serde(flatten)]
+    pub outcome: Outcome,
+}
+
+/// `$XDG_STATE_HOME/cpu-limiter/audit.jsonl`, else under `~/.local/state`.
+pub fn default_path() -> PathBuf {
+    let base = match std::env::var_os("XDG_STATE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME").unwrap_or_default()).join(".local/state"),
+    };
+    base.join("cpu-limiter").join("audit.jsonl")
+}
+
+/// Appends entries, moving the file to `.1`, `.2`, ... once it reaches `max_bytes`.
+pub struct AuditLog {
+    path: PathBuf,
+    max_bytes: u64,
+    files: usize, // Rotated files kept besides the current one
+    file: Option<File>,
+    size: u64,
+    users: HashMap<u32, String>,
+    warned: bool,
+}
+
+impl AuditLog {
+    pub fn new(path: PathBuf, max_bytes: u64, files: usize) -> Self {
+        Self {
+            path,
+            max_bytes,
+            files,
+            file: None,
+            size: 0,
+            users: HashMap::new(),
+            warned: false,
+        }
+    }
+
+    pub fn record(&mut self, entry: &Entry) {
+        let Ok(mut line) = serde_json::to_string(entry) else {
+            return;
+        };
+        line.push('\n');
+        if let Err(e) = self.append(line.as_bytes()) {
+            // Once is enough, the limiter keeps working without it
+            if !self.warned {
+                log::warn!("audit log {} unavailable: {}", self.path.display(), e);
+                self.warned = true;
+            }
+            self.file = None;
+        }
+    }
+
+    fn append(&mut self, line: &[u8]) -> std::io::Result<()> {
+        if self.file.is_none() {
+            self.open()?;
+        }
+        if self.size > 0 && self.size + line.len() as u64 > self.max_bytes {
+            self.file = None;
+            self.rotate()?;
+            self.open()?;
+        }
+        if let Some(file) = &mut self.file {
+            file.write_all(line)?;
+            self.size += line.len() as u64;
+        }
+        Ok(())
+    }
+
+    fn open(&mut self) -> std::io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = file.metadata()?.len();
+        self.file = Some(file);
+        Ok(())
+    }
+
+    fn rotate(&self) -> std::io::Result<()> {
+        if self.files == 0 {
+            return std::fs::remove_file(&self.path);
+        }
+        for index in (1..self.files).rev() {
+            let _ = std::fs::rename(rotated(&self.path, index), rotated(&self.path, index + 1));
+        }
+        std::fs::rename(&self.path, rotated(&self.path, 1))
+    }
+
+    /// Login name for `uid`, looked up once.
+    pub fn user_name(&mut self, uid: u32) -> String {
+        self.users
+            .entry(uid)
+            .or_insert_with(|| {
+                nix::unistd::User::from_uid(nix::unistd::Uid::from_raw(uid))
+                    .ok()
+                    .flatten()
+                    .map_or_else(|| uid.to_string(), |user| user.name)
+            })
+            .clone()
+    }
+}
+
+fn rotated(path: &Path, index: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", index));
+    PathBuf::from(name)
+}
+
+/// Every entry in `path` and its rotated files, oldest first. Unreadable
+/// lines are skipped.
+pub fn read(path: &Path, files: usize) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    let paths = (1..=files).rev().map(|index| rotated(path, index)).chain([path.to_path_buf()]);
+    for path in paths {
+        let Ok(file) = File::open(&path) else {
+            continue;
+        };
+        for line in BufReader::new(file).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if let Ok(entry) = serde_json::from_str(&line) {
+                entries.push(entry);
+            }
+        }
+    }
+    entries
+}
+
+/// Narrows entries down to a process (pid or part of the name) and a time range.
+#[derive(Clone, Debug, Default)]
+pub struct Filter {
+    pub process: String,
+    pub since: Option<DateTime<Local>>,
+    pub until: Option<DateTime<Local>>,
+}
+
+impl Filter {
+    pub fn matches(&self, entry: &Entry) -> bool {
+        let process = self.process.trim();
+        let process_matches = process.is_empty()
+            || process.parse::<i32>().is_ok_and(|pid| pid == entry.pid)
+            || entry.name.to_lowercase().contains(&process.to_lowercase());
+        process_matches
+            && self.since.is_none_or(|since| entry.time >= since)
+            && self.until.is_none_or(|until| entry.time <= until)
+    }
+}
+
+/// Parses "HH:MM" (today) or "YYYY-MM-DD HH:MM" in local time.
+pub fn parse_time(text: &str) -> Option<DateTime<Local>> {
+    let text = text.trim();
+    let (date, time) = match text.split_once(' ') {
+        Some((date, time)) => (NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()?, time),
+        None => (Local::now().date_naive(), text),
+    };
+    let time = NaiveTime::parse_from_str(time, "%H:%M").ok()?;
+    Local.from_local_datetime(&date.and_time(time)).earliest()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    fn entry(pid: i32, name: &str, time: DateTime<Local>) -> Entry {
+        Entry {
+            time,
+            action: Action::Freeze,
+            signal: None,
+            pid,
+            name: name.to_string(),
+            user: Some("alice".to_string()),
+            mode: LimiterMode::Global,
+            trigger: Trigger::GlobalLoad { load_percent: 93.5, limit_percent: 80 },
+            outcome: Outcome::Failed { errno: "ESRCH".to_string() },
+        }
+    }
+
+    #[test]
+    fn test_rotation_and_filter() {
+        let dir = TempDir::new("audit");
+        let path = dir.join("audit.jsonl");
+
+        let start = parse_time("2026-03-02 14:00").unwrap();
+        let line_len = serde_json::to_string(&entry(100, "rustc", start)).unwrap().len() as u64 + 1;
+        let mut log = AuditLog::new(path.clone(), line_len * 2, 2);
+        for minute in 0..7 {
+            let time = start + chrono::Duration::minutes(minute);
+            log.record(&entry(100 + minute as i32, if minute % 2 == 0 { "rustc" } else { "cargo" }, time));
+        }
+
+        // Two lines per file: the current one, .1 and .2; the oldest is gone
+        assert!(rotated(&path, 2).exists());
+        assert!(!rotated(&path, 3).exists());
+        let entries = read(&path, 2);
+        assert_eq!(entries.iter().map(|e| e.pid).collect::<Vec<_>>(), vec![102, 103, 104, 105, 106]);
+        assert_eq!(entries[0], entry(102, "rustc", start + chrono::Duration::minutes(2)));
+
+        let line = std::fs::read_to_string(&path).unwrap();
+        assert!(line.contains("\"outcome\":\"failed\",\"errno\":\"ESRCH\""));
+        assert!(line.contains("\"trigger\":{\"kind\":\"global_load\""));
+
+        let filter = Filter {
+            process: "RUST".to_string(),
+            since: parse_time("2026-03-02 14:03"),
+            until: None,
+        };
+        let matched: Vec<i32> = entries.iter().filter(|e| filter.matches(e)).map(|e| e.pid).collect();
+        assert_eq!(matched, vec![104, 106]);
+        let by_pid = Filter { process: "105".to_string(), ..Default::default() };
+        assert_eq!(entries.iter().filter(|e| by_pid.matches(e)).count(), 1);
+        assert!(parse_time("14:02").is_some());
+        assert!(parse_time("yesterday").is_none());
+    }
+}
@@ -0,0 +1,171 @@
+use crate::config;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const DESKTOP_FILE: &str = "cpu-limiter.desktop";
+const SERVICE: &str = "cpu-limiter.service";
+
+/// How the limiter starts at login on Linux.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Method {
+    Desktop, // XDG autostart entry, the window starts minimized to the tray
+    Systemd, // User service running `daemon`, for headless sessions
+}
+
+/// Autostart files under one config dir (`$XDG_CONFIG_HOME` normally).
+pub struct Autostart {
+    config_home: PathBuf,
+}
+
+impl Autostart {
+    pub fn new(config_home: PathBuf) -> Self {
+        Self { config_home }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(config::config_home())
+    }
+
+    fn desktop_path(&self) -> PathBuf {
+        self.config_home.join("autostart").join(DESKTOP_FILE)
+    }
+
+    fn unit_path(&self) -> PathBuf {
+        self.config_home.join("systemd/user").join(SERVICE)
+    }
+
+    /// The link `systemctl --user enable` would create.
+    fn wants_path(&self) -> PathBuf {
+        self.config_home.join("systemd/user/default.target.wants").join(SERVICE)
+    }
+
+    /// An entry with `Hidden=true` or a unit that is not enabled doesn't start anything.
+    pub fn installed(&self) -> Option<Method> {
+        if let Ok(entry) = std::fs::read_to_string(self.desktop_path())
+            && !entry.lines().any(|line| line.trim() == "Hidden=true")
+        {
+            return Some(Method::Desktop);
+        }
+        if self.unit_path().exists() && self.wants_path().symlink_metadata().is_ok() {
+            return Some(Method::Systemd);
+        }
+        None
+    }
+
+    /// Replaces any other method, so the limiter never starts twice.
+    pub fn install(&self, method: Method, exe: &Path) -> io::Result<()> {
+        self.remove()?;
+        match method {
+            Method::Desktop => write(&self.desktop_path(), &desktop_entry(exe)),
+            Method::Systemd => {
+                write(&self.unit_path(), &unit(exe))?;
+                let wants = self.wants_path();
+                if let Some(dir) = wants.parent() {
+                    std::fs::create_dir_all(dir)?;
+                }
+                std::os::unix::fs::symlink(Path::new("..").join(SERVICE), wants)
+            }
+        }
+    }
+
+    pub fn remove(&self) -> io::Result<()> {
+        for path in [self.desktop_path(), self.wants_path(), self.unit_path()] {
+            match std::fs::remove_file(path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write(path: &Path, contents: &str) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, contents)
+}
+
+fn desktop_entry(exe: &Path) -> String {
+    // Exec quoting, then string escaping on top, per the Desktop Entry spec;
+    // a literal `%` is doubled so it isn't read as a field code
+    let exe = exe
+        .to_string_lossy()
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('`', "\\`")
+        .replace('$', "\\$")
+        .replace('%', "%%");
+    format!(
+        "[Desktop Entry]
+Type=Application
+Name=CPU Limiter
+Comment=Limit the CPU usage of processes
+Exec=\"{}\" --minimized
+Terminal=false
+X-GNOME-Autostart-enabled=true
+",
+        exe.replace('\\', "\\\\")
+    )
+}
+
+fn unit(exe: &Path) -> String {
+    // `%` and `$` are specifiers and variables in ExecStart
+    let exe = exe.to_string_lossy().replace('\\', "\\\\").replace('"', "\\\"").replace('%', "%%").replace('$', "$$");
+    format!(
+        "[Unit]
+Description=CPU Limiter (headless)
+
+[Service]
+Type=notify
+ExecStart=\"{}\" daemon
+WatchdogSec=30
+Restart=on-failure
+
+[Install]
+WantedBy=default.target
+",
+        exe
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn test_install_and_detect() {
+        let dir = TempDir::new("autostart");
+        let autostart = Autostart::new(dir.to_path_buf());
+        let exe = Path::new("/opt/CPU Limiter/cpu_limiter");
+        assert_eq!(autostart.installed(), None);
+
+        autostart.install(Method::Desktop, exe).unwrap();
+        assert_eq!(autostart.installed(), Some(Method::Desktop));
+        let entry = std::fs::read_to_string(dir.join("autostart/cpu-limiter.desktop")).unwrap();
+        assert!(entry.contains("Exec=\"/opt/CPU Limiter/cpu_limiter\" --minimized"), "{}", entry);
+        let entry = desktop_entry(Path::new("/opt/100% CPU/cpu_limiter"));
+        assert!(entry.contains("Exec=\"/opt/100%% CPU/cpu_limiter\" --minimized"), "{}", entry);
+
+        autostart.install(Method::Systemd, exe).unwrap();
+        assert_eq!(autostart.installed(), Some(Method::Systemd));
+        assert!(!dir.join("autostart/cpu-limiter.desktop").exists());
+        let unit = std::fs::read_to_string(dir.join("systemd/user/cpu-limiter.service")).unwrap();
+        assert!(unit.contains("ExecStart=\"/opt/CPU Limiter/cpu_limiter\" daemon"), "{}", unit);
+
+        // Disabled with `systemctl --user disable`
+        std::fs::remove_file(dir.join("systemd/user/default.target.wants/cpu-limiter.service")).unwrap();
+        assert_eq!(autostart.installed(), None);
+
+        // Turned off in the desktop's startup settings
+        autostart.install(Method::Desktop, exe).unwrap();
+        let path = dir.join("autostart/cpu-limiter.desktop");
+        let entry = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(&path, entry + "Hidden=true\n").unwrap();
+        assert_eq!(autostart.installed(), None);
+
+        autostart.remove().unwrap();
+        assert!(!dir.join("systemd/user/cpu-limiter.service").exists());
+    }
+}
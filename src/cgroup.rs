@@ -0,0 +1,125 @@
+use anyhow::bail;
+
+/// Container runtimes by the scope prefix the systemd cgroup driver gives them.
+const SCOPE_PREFIXES: [(&str, &str); 4] =
+    [("docker-", "docker"), ("libpod-", "podman"), ("cri-containerd-", "containerd"), ("crio-", "cri-o")];
+const SHORT_ID: usize = 12;
+
+/// The cgroup a process is in, from `/proc/[pid]/cgroup`: its path in the
+/// unified hierarchy, or in the v1 cpu controller on older systems.
+pub fn parse(contents: &str) -> Option<String> {
+    let mut v1 = None;
+    for line in contents.lines() {
+        let mut fields = line.splitn(3, ':');
+        let (Some(id), Some(controllers), Some(path)) = (fields.next(), fields.next(), fields.next()) else {
+            continue;
+        };
+        if id == "0" && controllers.is_empty() {
+            return Some(path.to_string());
+        }
+        if controllers.split(',').any(|c| c == "cpu") {
+            v1 = Some(path.to_string());
+        }
+    }
+    v1
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Kind {
+    Container,
+    Unit, // systemd service or scope
+}
+
+/// A container or systemd unit, which the process list can be grouped by.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Group {
+    pub kind: Kind,
+    pub name: String, // "docker 0123456789ab", "nginx.service"
+    pub path: String, // Cgroup holding all of it
+}
+
+/// The container `path` is in, else the innermost systemd unit.
+pub fn group(path: &str) -> Option<Group> {
+    let segments: Vec<&str> = path.split('/').collect();
+    let prefix = |end: usize| segments[..=end].join("/");
+    let mut unit = None;
+    for (index, segment) in segments.iter().enumerate() {
+        // systemd driver: .../docker-<id>.scope
+        if let Some(stem) = segment.strip_suffix(".scope") {
+            for (scope_prefix, runtime) in SCOPE_PREFIXES {
+                if let Some(id) = stem.strip_prefix(scope_prefix).filter(|id| is_container_id(id)) {
+                    return Some(container(runtime, id, prefix(index)));
+                }
+            }
+        }
+        // cgroupfs driver: /docker/<id>
+        if index > 0 && segments[index - 1] == "docker" && is_container_id(segment) {
+            return Some(container("docker", segment, prefix(index)));
+        }
+        if segment.ends_with(".service") || segment.ends_with(".scope") {
+            unit = Some(Group { kind: Kind::Unit, name: segment.to_string(), path: prefix(index) });
+        }
+    }
+    unit
+}
+
+fn container(runtime: &str, id: &str, path: String) -> Group {
+    Group { kind: Kind::Container, name: format!("{} {}", runtime, &id[..SHORT_ID]), path }
+}
+
+fn is_container_id(id: &str) -> bool {
+    id.len() >= SHORT_ID && id.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Refuses targets that take in whole parts of the system: the root cgroup and
+/// top-level slices such as `/system.slice` or `/user.slice`.
+pub fn check_target(target: &str) -> anyhow::Result<()> {
+    let Some(path) = target.strip_prefix('/') else {
+        bail!("cgroup '{}' must be a path like /system.slice/nginx.service", target);
+    };
+    if !path.trim_matches('/').contains('/') {
+        bail!("cgroup '{}' would limit every process under it; name a unit or container", target);
+    }
+    Ok(())
+}
+
+/// Whether `path` is the cgroup `target` or one below it.
+pub fn contains(target: &str, path: &str) -> bool {
+    let target = target.trim_end_matches('/');
+    path.strip_prefix(target).is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_group() {
+        let id = "4f1c2a8e9b7d6c5a4f1c2a8e9b7d6c5a4f1c2a8e9b7d6c5a4f1c2a8e9b7d6c5a";
+        let docker = format!("/system.slice/docker-{}.scope", id);
+        assert_eq!(parse(&format!("0::{}\n", docker)), Some(docker.clone()));
+        assert_eq!(parse("12:cpuset:/\n4:cpu,cpuacct:/docker/abc\n1:name=systemd:/x\n"), Some("/docker/abc".to_string()));
+        assert_eq!(parse("garbage"), None);
+
+        let group = group(&format!("{}/init", docker)).unwrap();
+        assert_eq!((group.kind, group.name.as_str()), (Kind::Container, "docker 4f1c2a8e9b7d"));
+        assert_eq!(group.path, docker);
+        assert_eq!(super::group(&format!("/docker/{}", id)).unwrap().path, format!("/docker/{}", id));
+        assert_eq!(super::group("/machine.slice/libpod-0123456789abcdef.scope/container").unwrap().name, "podman 0123456789ab");
+
+        let unit = super::group("/system.slice/nginx.service").unwrap();
+        assert_eq!((unit.kind, unit.name.as_str(), unit.path.as_str()), (Kind::Unit, "nginx.service", "/system.slice/nginx.service"));
+        let app = super::group("/user.slice/user-1000.slice/user@1000.service/app.slice/app-firefox.scope").unwrap();
+        assert_eq!(app.name, "app-firefox.scope");
+        assert_eq!(super::group("/user.slice"), None);
+
+        assert!(contains("/system.slice/nginx.service", "/system.slice/nginx.service"));
+        assert!(contains("/system.slice/", "/system.slice/nginx.service"));
+        assert!(!contains("/system.slice/nginx.service", "/system.slice/nginx.service2"));
+
+        assert!(check_target("/system.slice/nginx.service").is_ok());
+        for target in ["", "/", "//", "/user.slice", "/system.slice/", "system.slice/nginx.service"] {
+            assert!(check_target(target).is_err(), "{}", target);
+        }
+    }
+}
@@ -0,0 +1,352 @@
+use crate::control::{Request, Response, StatusSnapshot};
+use crate::history::Format;
+use crate::limiter::LimiterMode;
+use anyhow::{Context, bail};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Gui { minimized: bool, force: bool }, // `force` takes over from a running instance
+    Daemon(DaemonOptions),
+    /// Sent to the running instance, or run standalone when there is none.
+    Control { request: Request, json: bool },
+    Run(RunOptions),
+    Export(ExportOptions),
+    Autostart(AutostartAction),
+}
+
+/// `autostart [status | gui | daemon | off]`, Linux only: `gui` installs the
+/// XDG autostart entry, `daemon` the systemd user service.
+#[derive(Debug, PartialEq)]
+pub enum AutostartAction {
+    Status,
+    Gui,
+    Daemon,
+    Off,
+}
+
+/// `export [--format csv|json] [--output FILE]`; the format defaults to the
+/// output's extension, else CSV.
+#[derive(Debug, PartialEq)]
+pub struct ExportOptions {
+    pub format: Format,
+    pub output: Option<PathBuf>, // stdout when missing
+}
+
+/// `run [--percent N] [--tree] -- COMMAND...`
+#[derive(Debug, PartialEq)]
+pub struct RunOptions {
+    pub percent: u32,
+    pub tree: bool, // Also limit everything the command spawns
+    pub command: Vec<String>,
+}
+
+/// Initial limits for a headless run; limiting starts right away if any is given.
+#[derive(Debug, Default, PartialEq)]
+pub struct DaemonOptions {
+    pub pid: Option<i32>,
+    pub http: Option<SocketAddr>, // Overrides CPU_LIMITER_HTTP
+    pub global: Option<u32>,
+    pub limit: Option<u32>,
+    pub names: Vec<String>,
+    pub cgroups: Vec<String>, // Cgroup paths, such as a container's scope
+    pub force: bool, // Take over from a running instance
+}
+
+pub const USAGE: &str = "\
+Usage:
+  cpu_limiter [--minimized | --background] [--force]
+  cpu_limiter daemon [--global PERCENT] [--name PROCESS]... [--cgroup PATH]... [--limit PERCENT] [--http ADDR] [--force]
+  cpu_limiter limit --pid PID --percent PERCENT
+  cpu_limiter global --percent PERCENT
+  cpu_limiter release [--pid PID]
+  cpu_limiter status [--json]
+  cpu_limiter stop
+  cpu_limiter events
+  cpu_limiter run --percent PERCENT [--tree] -- COMMAND [ARGS]...
+  cpu_limiter export [--format csv|json] [--output FILE]
+  cpu_limiter autostart [status | gui | daemon | off]";
+
+pub fn parse(args: impl IntoIterator<Item = String>) -> anyhow::Result<Command> {
+    let args: Vec<String> = args.into_iter().collect();
+    match args.first().map(String::as_str) {
+        Some("daemon") => parse_daemon(&args[1..]).map(Command::Daemon),
+        Some(name @ ("limit" | "global" | "release" | "status" | "stop" | "events")) => parse_control(name, &args[1..]),
+        Some("run") => parse_run(&args[1..]).map(Command::Run),
+        Some("export") => parse_export(&args[1..]).map(Command::Export),
+        Some("autostart") => {
+            let action = match args.get(1..).unwrap_or_default() {
+                [] => AutostartAction::Status,
+                [action] if action == "status" => AutostartAction::Status,
+                [action] if action == "gui" => AutostartAction::Gui,
+                [action] if action == "daemon" => AutostartAction::Daemon,
+                [action] if action == "off" => AutostartAction::Off,
+                _ => bail!("autostart takes one of status, gui, daemon or off\n{}", USAGE),
+            };
+            Ok(Command::Autostart(action))
+        }
+        Some("-h" | "--help" | "help") => bail!("{}", USAGE),
+        // The GUI ignores unknown arguments (e.g. -psn_ from Finder)
+        _ => Ok(Command::Gui {
+            minimized: args.iter().any(|arg| arg == "--minimized" || arg == "--background"),
+            force: args.iter().any(|arg| arg == "--force"),
+        }),
+    }
+}
+
+fn parse_percent(flag: &str, value: Option<&String>) -> anyhow::Result<u32> {
+    let value = value.with_context(|| format!("{} needs a value", flag))?;
+    let percent: u32 = value
+        .trim_end_matches('%')
+        .parse()
+        .with_context(|| format!("{}: '{}' is not a percentage", flag, value))?;
+    if !(1..=100).contains(&percent) {
+        bail!("{}: {} is outside 1-100", flag, percent);
+    }
+    Ok(percent)
+}
+
+fn parse_pid(flag: &str, value: Option<&String>) -> anyhow::Result<i32> {
+    let value = value.with_context(|| format!("{} needs a value", flag))?;
+    match value.parse::<i32>() {
+        Ok(pid) if pid > 0 => Ok(pid),
+        _ => bail!("{}: '{}' is not a PID", flag, value),
+    }
+}
+
+fn parse_control(name: &str, args: &[String]) -> anyhow::Result<Command> {
+    let mut pid = None;
+    let mut percent = None;
+    let mut json = false;
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        match (name, arg.as_str()) {
+            ("limit" | "release", "--pid") => pid = Some(parse_pid(arg, rest.next())?),
+            ("limit" | "global", "--percent") => percent = Some(parse_percent(arg, rest.next())?),
+            ("status", "--json") => json = true,
+            (_, other) => bail!("unknown {} option '{}'\n{}", name, other, USAGE),
+        }
+    }
+
+    let request = match name {
+        "limit" => Request::Limit {
+            pid: pid.context("limit needs --pid")?,
+            percent: percent.context("limit needs --percent")?,
+        },
+        "global" => Request::Global {
+            percent: percent.context("global needs --percent")?,
+        },
+        "release" => Request::Release { pid },
+        "status" => Request::Status,
+        "events" => Request::Subscribe,
+        _ => Request::Stop,
+    };
+    Ok(Command::Control { request, json })
+}
+
+fn parse_run(args: &[String]) -> anyhow::Result<RunOptions> {
+    let mut percent = None;
+    let mut tree = false;
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--percent" => percent = Some(parse_percent(arg, rest.next())?),
+            "--tree" => tree = true,
+            "--" => break,
+            other if other.starts_with('-') => bail!("unknown run option '{}'\n{}", other, USAGE),
+            other => {
+                // The command starts at the first non-option, `--` is optional
+                let command = std::iter::once(other.to_string()).chain(rest.cloned()).collect();
+                return Ok(RunOptions {
+                    percent: percent.context("run needs --percent")?,
+                    tree,
+                    command,
+                });
+            }
+        }
+    }
+    let command: Vec<String> = rest.cloned().collect();
+    if command.is_empty() {
+        bail!("run needs a command\n{}", USAGE);
+    }
+    Ok(RunOptions {
+        percent: percent.context("run needs --percent")?,
+        tree,
+        command,
+    })
+}
+
+fn parse_export(args: &[String]) -> anyhow::Result<ExportOptions> {
+    let mut format = None;
+    let mut output = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => format = Some(args.next().with_context(|| format!("{} needs a value", arg))?.parse()?),
+            "--output" | "-o" => {
+                output = Some(PathBuf::from(args.next().with_context(|| format!("{} needs a value", arg))?))
+            }
+            other => bail!("unknown export option '{}'\n{}", other, USAGE),
+        }
+    }
+    let format = format
+        .or_else(|| output.as_deref().and_then(Format::from_path))
+        .unwrap_or(Format::Csv);
+    Ok(ExportOptions { format, output })
+}
+
+fn parse_daemon(args: &[String]) -> anyhow::Result<DaemonOptions> {
+    let mut options = DaemonOptions::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--global" => options.global = Some(parse_percent(arg, args.next())?),
+            "--limit" => options.limit = Some(parse_percent(arg, args.next())?),
+            "--http" => {
+                let addr = args.next().with_context(|| format!("{} needs a value", arg))?;
+                options.http = Some(crate::http::parse_address(addr)?);
+            }
+            "--name" => options
+                .names
+                .push(args.next().with_context(|| format!("{} needs a value", arg))?.clone()),
+            "--cgroup" => {
+                let target = args.next().with_context(|| format!("{} needs a value", arg))?;
+                crate::cgroup::check_target(target)?;
+                options.cgroups.push(target.clone());
+            }
+            "--force" => options.force = true,
+            other => bail!("unknown daemon option '{}'\n{}", other, USAGE),
+        }
+    }
+    Ok(options)
+}
+
+pub fn print_response(response: &Response, json: bool) -> anyhow::Result<()> {
+    match response {
+        Response::Ok => {}
+        Response::Error { message } => bail!("{}", message),
+        Response::Status(snapshot) if json => println!("{}", serde_json::to_string_pretty(snapshot)?),
+        Response::Status(snapshot) => print_status(snapshot),
+        Response::History { samples } => println!("{}", serde_json::to_string_pretty(samples)?),
+    }
+    Ok(())
+}
+
+fn print_status(snapshot: &StatusSnapshot) {
+    let status = &snapshot.status;
+    let mode = match snapshot.mode {
+        LimiterMode::Targeted => "targeted",
+        LimiterMode::Global => "global",
+    };
+    println!("limiter:  {}", if snapshot.is_active { "active" } else { "inactive" });
+    println!("mode:     {} ({}%)", mode, snapshot.limit_percentage);
+    if let Some(pid) = status.target_pid {
+        println!("target:   {}", pid);
+    }
+    println!("paused:   {} processes, {} pauses so far", status.currently_paused_pids.len(), status.pause_count);
+    if let Some(schedule) = &status.active_schedule {
+        println!("schedule: {}", schedule);
+    }
+    if let Some(variant) = &status.power_variant {
+        println!("power:    {}", variant);
+    }
+    if let Some(temperature) = status.temperature_c {
+        let note = if status.thermal_throttling { ", throttling" } else { "" };
+        println!("temp:     {:.1}°C{}", temperature, note);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(parse(args("")).unwrap(), Command::Gui { minimized: false, force: false });
+        assert_eq!(parse(args("-psn_0_1 --background")).unwrap(), Command::Gui { minimized: true, force: false });
+        assert_eq!(parse(args("--force")).unwrap(), Command::Gui { minimized: false, force: true });
+        assert_eq!(
+            parse(args("daemon --global 70% --name cargo --name rustc --cgroup /system.slice/nginx.service --limit 30")).unwrap(),
+            Command::Daemon(DaemonOptions {
+                pid: None,
+                http: None,
+                global: Some(70),
+                limit: Some(30),
+                names: vec!["cargo".to_string(), "rustc".to_string()],
+                cgroups: vec!["/system.slice/nginx.service".to_string()],
+                force: false,
+            })
+        );
+        assert!(matches!(parse(args("daemon --force")), Ok(Command::Daemon(DaemonOptions { force: true, .. }))));
+        assert!(parse(args("daemon --global 0")).is_err());
+        assert!(parse(args("daemon --global")).is_err());
+        assert!(parse(args("daemon --cgroup")).is_err());
+        assert!(parse(args("daemon --cgroup /")).is_err());
+        assert!(parse(args("daemon --cgroup /user.slice")).is_err());
+        assert!(parse(args("daemon --verbose")).is_err());
+    }
+
+    #[test]
+    fn test_parse_control_commands() {
+        assert_eq!(
+            parse(args("limit --pid 1234 --percent 30")).unwrap(),
+            Command::Control { request: Request::Limit { pid: 1234, percent: 30 }, json: false }
+        );
+        assert_eq!(
+            parse(args("release")).unwrap(),
+            Command::Control { request: Request::Release { pid: None }, json: false }
+        );
+        assert_eq!(
+            parse(args("status --json")).unwrap(),
+            Command::Control { request: Request::Status, json: true }
+        );
+        assert!(parse(args("limit --pid 1234")).is_err());
+        assert!(parse(args("global --pid 1234 --percent 80")).is_err());
+        assert!(parse(args("stop --json")).is_err());
+    }
+
+    #[test]
+    fn test_parse_export() {
+        assert_eq!(
+            parse(args("export --output evidence.json")).unwrap(),
+            Command::Export(ExportOptions { format: Format::Json, output: Some(PathBuf::from("evidence.json")) })
+        );
+        assert_eq!(
+            parse(args("export --format json -o out.txt")).unwrap(),
+            Command::Export(ExportOptions { format: Format::Json, output: Some(PathBuf::from("out.txt")) })
+        );
+        assert_eq!(parse(args("export")).unwrap(), Command::Export(ExportOptions { format: Format::Csv, output: None }));
+        assert!(parse(args("export --format xml")).is_err());
+    }
+
+    #[test]
+    fn test_parse_autostart() {
+        assert_eq!(parse(args("autostart")).unwrap(), Command::Autostart(AutostartAction::Status));
+        assert_eq!(parse(args("autostart daemon")).unwrap(), Command::Autostart(AutostartAction::Daemon));
+        assert!(parse(args("autostart daemon gui")).is_err());
+        assert!(parse(args("autostart on")).is_err());
+    }
+
+    #[test]
+    fn test_parse_run() {
+        assert_eq!(
+            parse(args("run --percent 40 --tree -- make -j8 --keep-going")).unwrap(),
+            Command::Run(RunOptions {
+                percent: 40,
+                tree: true,
+                command: args("make -j8 --keep-going"),
+            })
+        );
+        assert_eq!(
+            parse(args("run --percent 40 cargo build")).unwrap(),
+            Command::Run(RunOptions { percent: 40, tree: false, command: args("cargo build") })
+        );
+        assert!(parse(args("run --percent 40 --")).is_err());
+        assert!(parse(args("run -- make")).is_err());
+    }
+}
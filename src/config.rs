@@ -0,0 +1,433 @@
+use crate::audit::{self, AuditLog};
+use crate::cgroup;
+use crate::indicator;
+use crate::limiter::{DEFAULT_CHECK_INTERVAL, EscalationPolicy, Limiter, LimiterMode, LimiterState, MemoryRule};
+use crate::power::{PowerVariant, PowerVariants};
+use crate::schedule::Schedule;
+use crate::thermal::ThermalTrigger;
+use anyhow::{Context, bail};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Settings kept in `$XDG_CONFIG_HOME/cpu-limiter/config.toml`.
+///
+/// Missing keys take their defaults, unknown keys are an error so typos do
+/// not go unnoticed.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub active: bool,
+    pub limiter: LimiterSettings,
+    pub profiles: Vec<Profile>,
+    pub refresh: RefreshSettings,
+    pub audit: AuditSettings,
+    pub tray: TraySettings,
+    pub ui: UiSettings,
+}
+
+/// The persistent part of `LimiterState`, minus whether it is switched on.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimiterSettings {
+    pub mode: LimiterMode,
+    pub limit_percentage: u32,
+    pub target_names: Vec<String>,
+    pub target_cgroups: Vec<String>,
+    pub include_children: bool,
+    pub protected: Vec<String>,
+    pub escalation: Option<EscalationPolicy>,
+    pub memory_rules: Vec<MemoryRule>,
+    pub schedules: Vec<Schedule>,
+    pub power: PowerVariants,
+    pub thermal: Option<ThermalTrigger>,
+}
+
+impl Default for LimiterSettings {
+    fn default() -> Self {
+        Self {
+            mode: LimiterMode::Targeted,
+            limit_percentage: 50,
+            target_names: Vec::new(),
+            target_cgroups: Vec::new(),
+            include_children: false,
+            protected: Vec::new(),
+            escalation: None,
+            memory_rules: Vec::new(),
+            schedules: Vec::new(),
+            power: PowerVariants::default(),
+            thermal: None,
+        }
+    }
+}
+
+/// A named `LimiterSettings`, e.g. "meeting" or "compiling".
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub name: String,
+    pub limiter: LimiterSettings,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RefreshSettings {
+    pub process_list_ms: u64, // Window process list and stat cards
+    pub check_interval_ms: u64,
+}
+
+impl Default for RefreshSettings {
+    fn default() -> Self {
+        Self {
+            process_list_ms: 1000,
+            check_interval_ms: DEFAULT_CHECK_INTERVAL.as_millis() as u64,
+        }
+    }
+}
+
+/// The JSON Lines log of pause/resume actions, see `audit`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuditSettings {
+    pub enabled: bool,
+    pub max_bytes: u64, // Rotate once the file reaches this size
+    pub files: usize,   // Rotated files kept
+}
+
+impl Default for AuditSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_bytes: audit::DEFAULT_MAX_BYTES,
+            files: audit::DEFAULT_FILES,
+        }
+    }
+}
+
+impl AuditSettings {
+    pub fn open(&self) -> Option<AuditLog> {
+        self.enabled.then(|| AuditLog::new(audit::default_path(), self.max_bytes, self.files))
+    }
+}
+
+/// Tray icon and texts; templates take `{cpu}`, `{memory}`, `{paused}` and `{profile}`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TraySettings {
+    pub dynamic_icon: bool, // CPU graph with a state badge instead of tray.png
+    pub title: String,      // Next to the icon, where the panel shows titles
+    pub tooltip: String,
+}
+
+impl Default for TraySettings {
+    fn default() -> Self {
+        Self {
+            dynamic_icon: true,
+            title: "{cpu}%".to_string(),
+            tooltip: "CPU Limiter {profile}\nCPU {cpu}% • RAM {memory}% • {paused} paused".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UiSettings {
+    pub filter: String,
+    pub selected: Option<SelectedProcess>,
+    pub group: bool, // Processes listed by container or systemd unit
+}
+
+/// Restored only if the same pid still runs under the same name.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SelectedProcess {
+    pub pid: i32,
+    pub name: String,
+}
+
+impl LimiterSettings {
+    pub fn from_state(state: &LimiterState) -> Self {
+        Self {
+            mode: state.mode,
+            limit_percentage: state.limit_percentage,
+            target_names: state.target_names.clone(),
+            target_cgroups: state.target_cgroups.clone(),
+            include_children: state.include_children,
+            protected: state.protected.clone(),
+            escalation: state.escalation.clone(),
+            memory_rules: state.memory_rules.clone(),
+            schedules: state.schedules.clone(),
+            power: state.power,
+            thermal: state.thermal.clone(),
+        }
+    }
+
+    pub fn apply(&self, limiter: &Limiter) {
+        limiter.update(|state| self.write_to(state));
+    }
+
+    fn write_to(&self, state: &mut LimiterState) {
+        state.mode = self.mode;
+        state.limit_percentage = self.limit_percentage.clamp(1, 100);
+        state.target_names = self.target_names.clone();
+        state.target_cgroups = self.target_cgroups.clone();
+        state.include_children = self.include_children;
+        state.protected = self.protected.clone();
+        state.escalation = self.escalation.clone();
+        state.memory_rules = self.memory_rules.clone();
+        state.schedules = self.schedules.clone();
+        state.power = self.power;
+        state.thermal = self.thermal.clone();
+    }
+
+    fn validate(&self, prefix: &str) -> anyhow::Result<()> {
+        check_percent(&format!("{}.limit_percentage", prefix), self.limit_percentage)?;
+        for target in &self.target_cgroups {
+            cgroup::check_target(target).with_context(|| format!("{}.target_cgroups", prefix))?;
+        }
+        if let Some(policy) = &self.escalation {
+            check_percent(&format!("{}.escalation.throttle_percent", prefix), policy.throttle_percent)?;
+            if policy.threshold_percent <= 0.0 {
+                bail!("{}.escalation.threshold_percent must be positive", prefix);
+            }
+        }
+        if let Some(thermal) = &self.thermal {
+            check_percent(&format!("{}.thermal.throttle_percent", prefix), thermal.throttle_percent)?;
+            if thermal.hysteresis_c < 0.0 {
+                bail!("{}.thermal.hysteresis_c must not be negative", prefix);
+            }
+        }
+        check_variant(&format!("{}.power.on_ac", prefix), &self.power.on_ac)?;
+        check_variant(&format!("{}.power.on_battery", prefix), &self.power.on_battery)?;
+        if let Some(saver) = &self.power.battery_saver {
+            check_percent(&format!("{}.power.battery_saver.below_percent", prefix), saver.below_percent as u32)?;
+            check_variant(&format!("{}.power.battery_saver.variant", prefix), &Some(saver.variant))?;
+        }
+        for schedule in &self.schedules {
+            if schedule.name.trim().is_empty() {
+                bail!("{}: schedule '{}' needs a name", prefix, schedule.when);
+            }
+            check_percent(
+                &format!("{}: schedule '{}' limit_percentage", prefix, schedule.name),
+                schedule.config.limit_percentage,
+            )?;
+            for target in &schedule.config.target_cgroups {
+                cgroup::check_target(target)
+                    .with_context(|| format!("{}: schedule '{}' target_cgroups", prefix, schedule.name))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Profile {
+    /// Replaces the limiter's settings in one step; on its next period the
+    /// worker resumes whatever the old settings paused and the new ones do
+    /// not cover. A pid target is dropped, profiles target by name.
+    pub fn switch(&self, limiter: &Limiter) {
+        limiter.update(|state| {
+            self.limiter.write_to(state);
+            state.target_pid = None;
+        });
+        log::info!("switched to profile '{}'", self.name);
+    }
+}
+
+fn check_percent(field: &str, percent: u32) -> anyhow::Result<()> {
+    if !(1..=100).contains(&percent) {
+        bail!("{} = {} is outside 1-100", field, percent);
+    }
+    Ok(())
+}
+
+fn check_variant(field: &str, variant: &Option<PowerVariant>) -> anyhow::Result<()> {
+    match variant {
+        Some(variant) => check_percent(&format!("{}.limit_percentage", field), variant.limit_percentage),
+        None => Ok(()),
+    }
+}
+
+impl Config {
+    /// Catches what parses but cannot work; schedules are checked on parse.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        self.limiter.validate("limiter")?;
+        for (index, profile) in self.profiles.iter().enumerate() {
+            if profile.name.trim().is_empty() {
+                bail!("profiles[{}] needs a name", index);
+            }
+            if self.profiles[..index].iter().any(|p| p.name == profile.name) {
+                bail!("profile '{}' is defined twice", profile.name);
+            }
+            profile.limiter.validate(&format!("profile '{}'", profile.name))?;
+        }
+        if self.refresh.process_list_ms < 100 || self.refresh.check_interval_ms < 100 {
+            bail!("refresh intervals must be at least 100 ms");
+        }
+        if self.audit.max_bytes < 4096 {
+            bail!("audit.max_bytes must be at least 4096");
+        }
+        indicator::check_template(&self.tray.title).context("tray.title")?;
+        indicator::check_template(&self.tray.tooltip).context("tray.tooltip")?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+        let config: Config = toml::from_str(&text).with_context(|| format!("invalid {}", path.display()))?;
+        config.validate().with_context(|| format!("invalid {}", path.display()))?;
+        Ok(config)
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let text = toml::to_string_pretty(self)?;
+        // Write then rename, so a reload never sees half a file
+        let tmp = path.with_extension("toml.tmp");
+        std::fs::write(&tmp, text)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Applies everything the limiter itself uses.
+    pub fn apply(&self, limiter: &Limiter) {
+        self.limiter.apply(limiter);
+        limiter.set_check_interval(Duration::from_millis(self.refresh.check_interval_ms));
+        limiter.set_audit_log(self.audit.open());
+        limiter.toggle(self.active);
+    }
+}
+
+/// `CPU_LIMITER_CONFIG`, else `cpu-limiter/config.toml` in the XDG config dir.
+pub fn default_path() -> PathBuf {
+    if let Some(path) = std::env::var_os("CPU_LIMITER_CONFIG") {
+        return PathBuf::from(path);
+    }
+    config_home().join("cpu-limiter").join("config.toml")
+}
+
+/// `$XDG_CONFIG_HOME`, else `~/.config`.
+pub fn config_home() -> PathBuf {
+    match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME").unwrap_or_default()).join(".config"),
+    }
+}
+
+/// Tracks the file's mtime so external edits can be picked up by polling.
+pub struct ConfigFile {
+    path: PathBuf,
+    seen: Option<SystemTime>,
+}
+
+impl ConfigFile {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path, seen: None }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        std::fs::metadata(&self.path).and_then(|m| m.modified()).ok()
+    }
+
+    /// The file's contents if it changed since the last poll or save.
+    pub fn poll(&mut self) -> Option<anyhow::Result<Config>> {
+        let modified = self.modified();
+        if modified == self.seen {
+            return None;
+        }
+        self.seen = modified;
+        modified.map(|_| Config::load(&self.path))
+    }
+
+    pub fn save(&mut self, config: &Config) -> anyhow::Result<()> {
+        config.save(&self.path)?;
+        self.seen = self.modified();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limiter::{MemoryAction, MemoryTrigger};
+    use crate::testing::TempDir;
+
+    #[test]
+    fn test_round_trip_and_validation() {
+        let dir = TempDir::new("config");
+        let path = dir.join("config.toml");
+
+        let mut config = Config::default();
+        config.limiter.mode = LimiterMode::Global;
+        config.limiter.escalation = Some(EscalationPolicy::default());
+        config.limiter.memory_rules.push(MemoryRule {
+            trigger: MemoryTrigger::AvailableBelow { bytes: 1 << 30 },
+            action: MemoryAction::Pause,
+        });
+        config.limiter.schedules.push(Schedule {
+            name: "work".to_string(),
+            when: "Mon-Fri 09:00-18:00".parse().unwrap(),
+            config: Limiter::new().get_state().config(),
+        });
+        config.ui.selected = Some(SelectedProcess { pid: 42, name: "cargo".to_string() });
+        config.profiles.push(Profile {
+            name: "compiling".to_string(),
+            limiter: LimiterSettings {
+                target_names: vec!["rustc".to_string(), "cc1plus".to_string()],
+                limit_percentage: 40,
+                ..Default::default()
+            },
+        });
+
+        let mut file = ConfigFile::new(path.clone());
+        assert!(file.poll().is_none(), "no file yet");
+        file.save(&config).unwrap();
+        assert!(file.poll().is_none(), "own writes are not reloads");
+        assert_eq!(Config::load(&path).unwrap(), config);
+
+        // Partial files fill in defaults
+        let partial: Config = toml::from_str("[limiter]\nlimit_percentage = 30\n").unwrap();
+        assert_eq!(partial.limiter.limit_percentage, 30);
+        assert_eq!(partial.refresh, RefreshSettings::default());
+
+        assert!(toml::from_str::<Config>("[limiter]\nlimit = 30\n").is_err());
+        std::fs::write(&path, "[limiter]\nlimit_percentage = 0\n").unwrap();
+        let error = Config::load(&path).unwrap_err();
+        assert!(format!("{:#}", error).contains("outside 1-100"));
+        let mut twice = config.clone();
+        twice.profiles.push(twice.profiles[0].clone());
+        assert!(twice.validate().is_err());
+        std::fs::write(&path, "[limiter]\ntarget_cgroups = [\"/\"]\n").unwrap();
+        assert!(format!("{:#}", Config::load(&path).unwrap_err()).contains("limiter.target_cgroups"));
+        std::fs::write(&path, "[tray]\ntitle = \"{load}%\"\n").unwrap();
+        assert!(format!("{:#}", Config::load(&path).unwrap_err()).contains("tray.title"));
+        std::fs::write(&path, "[[limiter.schedules]]\nname = \"x\"\nwhen = \"Someday\"\n").unwrap();
+        assert!(Config::load(&path).is_err());
+    }
+
+    #[test]
+    fn test_profile_switch() {
+        let limiter = Limiter::new();
+        limiter.set_global(80);
+        limiter.set_target(1234).unwrap();
+        let profile = Profile {
+            name: "meeting".to_string(),
+            limiter: LimiterSettings {
+                target_names: vec!["zoom".to_string()],
+                limit_percentage: 20,
+                ..Default::default()
+            },
+        };
+        profile.switch(&limiter);
+
+        let state = limiter.get_state();
+        assert_eq!(state.mode, LimiterMode::Targeted);
+        assert_eq!(state.limit_percentage, 20);
+        assert_eq!(state.target_pid, None);
+        assert_eq!(LimiterSettings::from_state(&state), profile.limiter);
+    }
+}
@@ -0,0 +1,188 @@
+use nix::sys::socket::{self, MsgFlags, NetlinkAddr, sockopt};
+use nix::sys::time::{TimeVal, TimeValLike};
+use parking_lot::Mutex;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// linux/connector.h and linux/cn_proc.h
+const CN_IDX_PROC: u32 = 1;
+const CN_VAL_PROC: u32 = 1;
+const PROC_CN_MCAST_LISTEN: u32 = 1;
+const PROC_EVENT_NONE: u32 = 0; // Acknowledges the subscription
+const PROC_EVENT_FORK: u32 = 0x1;
+const PROC_EVENT_EXEC: u32 = 0x2;
+const PROC_EVENT_EXIT: u32 = 0x8000_0000;
+
+const NLMSG_HEADER: usize = 16;
+const CN_MSG_HEADER: usize = 20;
+const EVENT_DATA: usize = 16; // what, cpu, timestamp_ns
+
+const ACK_TIMEOUT: Duration = Duration::from_secs(1);
+const POLL_TIMEOUT: Duration = Duration::from_millis(500); // How soon the reader notices a stop
+
+/// A process coming or going, as the kernel reports it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    Fork { parent: i32, child: i32 },
+    Exec { pid: i32 },
+    Exit { pid: i32 },
+}
+
+/// Subscribes on a thread of its own, so a slow acknowledgement holds up
+/// nobody, and queues events until `stop` is set. Nothing is ever queued if
+/// the subscription fails; callers keep polling either way.
+pub fn watch(stop: Arc<AtomicBool>) -> Arc<Mutex<Vec<Event>>> {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let queue = events.clone();
+    thread::spawn(move || {
+        let connector = match Connector::subscribe() {
+            Ok(connector) => connector,
+            Err(e) => return log::info!("proc connector unavailable ({}), polling for new processes", e),
+        };
+        log::info!("watching process starts and exits through the proc connector");
+        let mut buf = vec![0; 4096];
+        while !stop.load(Ordering::Relaxed) {
+            match connector.recv(&mut buf) {
+                Ok(Some(Message::Event(event))) => queue.lock().push(event),
+                Ok(_) => {}
+                // Events were dropped; polling picks up the slack
+                Err(e) if e.raw_os_error() == Some(libc::ENOBUFS) => {}
+                Err(e) => return log::warn!("process events stopped: {}", e),
+            }
+        }
+    });
+    events
+}
+
+/// A netlink socket subscribed to the kernel's proc connector.
+struct Connector {
+    socket: OwnedFd,
+}
+
+impl Connector {
+    /// Fails without `CAP_NET_ADMIN`, or on kernels without `CONFIG_PROC_EVENTS`,
+    /// which take the subscription but never acknowledge it.
+    fn subscribe() -> io::Result<Self> {
+        // SAFETY: a plain socket(2) call; the fd is owned from here on.
+        let fd = unsafe {
+            libc::socket(libc::AF_NETLINK, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, libc::NETLINK_CONNECTOR)
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: `fd` was just created and nothing else owns it.
+        let socket = unsafe { OwnedFd::from_raw_fd(fd) };
+        socket::bind(socket.as_raw_fd(), &NetlinkAddr::new(0, CN_IDX_PROC))?;
+        socket::setsockopt(&socket, sockopt::ReceiveTimeout, &TimeVal::milliseconds(POLL_TIMEOUT.as_millis() as i64))?;
+
+        let mut message = Vec::with_capacity(NLMSG_HEADER + CN_MSG_HEADER + 4);
+        let len = (NLMSG_HEADER + CN_MSG_HEADER + 4) as u32;
+        message.extend_from_slice(&len.to_ne_bytes());
+        message.extend_from_slice(&(libc::NLMSG_DONE as u16).to_ne_bytes());
+        message.extend_from_slice(&[0; 10]); // flags, seq, port
+        for value in [CN_IDX_PROC, CN_VAL_PROC, 0, 0] {
+            message.extend_from_slice(&value.to_ne_bytes());
+        }
+        message.extend_from_slice(&4u16.to_ne_bytes());
+        message.extend_from_slice(&0u16.to_ne_bytes());
+        message.extend_from_slice(&PROC_CN_MCAST_LISTEN.to_ne_bytes());
+        socket::send(socket.as_raw_fd(), &message, MsgFlags::empty())?;
+
+        let connector = Self { socket };
+        let deadline = Instant::now() + ACK_TIMEOUT;
+        let mut buf = vec![0; 4096];
+        while Instant::now() < deadline {
+            match connector.recv(&mut buf)? {
+                Some(Message::Ack(0)) => return Ok(connector),
+                Some(Message::Ack(errno)) => return Err(io::Error::from_raw_os_error(errno as i32)),
+                _ => {}
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::TimedOut, "proc connector did not acknowledge"))
+    }
+
+    /// `None` when nothing arrived within `POLL_TIMEOUT`.
+    fn recv(&self, buf: &mut [u8]) -> io::Result<Option<Message>> {
+        match socket::recv(self.socket.as_raw_fd(), buf, MsgFlags::empty()) {
+            Ok(len) => Ok(parse(&buf[..len])),
+            Err(nix::errno::Errno::EAGAIN | nix::errno::Errno::EINTR) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+}
+
+#[derive(Debug, PartialEq)]
+enum Message {
+    Ack(u32),
+    Event(Event),
+}
+
+/// One datagram: netlink header, connector header, then a `proc_event`.
+/// Thread events are left out, only whole processes are reported.
+fn parse(datagram: &[u8]) -> Option<Message> {
+    let word = |offset: usize| -> Option<u32> {
+        Some(u32::from_ne_bytes(datagram.get(offset..offset + 4)?.try_into().ok()?))
+    };
+    if word(NLMSG_HEADER)? != CN_IDX_PROC || word(NLMSG_HEADER + 4)? != CN_VAL_PROC {
+        return None;
+    }
+    let event = NLMSG_HEADER + CN_MSG_HEADER;
+    let data = |index: usize| word(event + EVENT_DATA + index * 4);
+    match word(event)? {
+        PROC_EVENT_NONE => Some(Message::Ack(data(0)?)),
+        PROC_EVENT_FORK => {
+            let (parent, child, child_tgid) = (data(1)?, data(2)?, data(3)?);
+            (child == child_tgid).then_some(Message::Event(Event::Fork { parent: parent as i32, child: child as i32 }))
+        }
+        PROC_EVENT_EXEC => Some(Message::Event(Event::Exec { pid: data(1)? as i32 })),
+        PROC_EVENT_EXIT => {
+            let (pid, tgid) = (data(0)?, data(1)?);
+            (pid == tgid).then_some(Message::Event(Event::Exit { pid: pid as i32 }))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn datagram(what: u32, data: &[u32]) -> Vec<u8> {
+        let mut bytes = vec![0; NLMSG_HEADER];
+        for value in [CN_IDX_PROC, CN_VAL_PROC, 0, 0] {
+            bytes.extend_from_slice(&value.to_ne_bytes());
+        }
+        bytes.extend_from_slice(&[0; 4]); // len, flags
+        bytes.extend_from_slice(&what.to_ne_bytes());
+        bytes.extend_from_slice(&[0; 12]); // cpu, timestamp
+        for value in data {
+            bytes.extend_from_slice(&value.to_ne_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_parse_events() {
+        assert_eq!(parse(&datagram(PROC_EVENT_NONE, &[0])), Some(Message::Ack(0)));
+        assert_eq!(parse(&datagram(PROC_EVENT_NONE, &[1])), Some(Message::Ack(1)));
+        assert_eq!(
+            parse(&datagram(PROC_EVENT_FORK, &[10, 10, 11, 11])),
+            Some(Message::Event(Event::Fork { parent: 10, child: 11 }))
+        );
+        // A new thread of 10
+        assert_eq!(parse(&datagram(PROC_EVENT_FORK, &[10, 10, 12, 10])), None);
+        assert_eq!(parse(&datagram(PROC_EVENT_EXEC, &[11, 11])), Some(Message::Event(Event::Exec { pid: 11 })));
+        assert_eq!(parse(&datagram(PROC_EVENT_EXIT, &[11, 11, 0, 17])), Some(Message::Event(Event::Exit { pid: 11 })));
+        assert_eq!(parse(&datagram(PROC_EVENT_EXIT, &[12, 10, 0, 0])), None);
+        // Truncated, or from another connector
+        assert_eq!(parse(&datagram(PROC_EVENT_EXEC, &[])), None);
+        let mut other = datagram(PROC_EVENT_EXEC, &[11, 11]);
+        other[NLMSG_HEADER] = 7;
+        assert_eq!(parse(&other), None);
+    }
+}
@@ -0,0 +1,494 @@
+use crate::history::Sample;
+use crate::limiter::{EscalationRecord, Limiter, LimiterMode, LimiterStatus};
+use nix::errno::Errno;
+use nix::sys::socket::{self, MsgFlags};
+use serde::{Deserialize, Serialize};
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// Bumped on incompatible changes; both sides put it on every line.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+const EVENT_POLL: Duration = Duration::from_millis(500);
+
+/// Every line on the socket is one JSON object: `{"version": 1, ...}`.
+#[derive(Debug, Serialize, Deserialize)]
+struct Message<T> {
+    version: u32,
+    #[serde(flatten)]
+    body: T,
+}
+
+/// One request per line; the instance answers each with one response line.
+/// After `Subscribe` the connection carries only events.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Request {
+    Limit { pid: i32, percent: u32 },
+    Global { percent: u32 },
+    Release { pid: Option<i32> }, // None stops limiting altogether
+    SetTarget { pid: i32 },
+    ClearTarget,
+    SetTargetNames { names: Vec<String> },
+    SetMode { mode: LimiterMode },
+    SetLimit { percent: u32 },
+    Toggle { active: bool },
+    Status,
+    History,
+    Subscribe,
+    Activate { args: Vec<String> }, // Arguments of a second launch
+    Stop,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "result", rename_all = "snake_case")]
+pub enum Response {
+    Ok,
+    Status(StatusSnapshot),
+    History { samples: Vec<Sample> },
+    Error { message: String },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StatusSnapshot {
+    pub mode: LimiterMode,
+    pub limit_percentage: u32,
+    pub is_active: bool,
+    pub status: LimiterStatus,
+}
+
+/// Pushed to subscribers when the limiter changes.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    StateChanged {
+        mode: LimiterMode,
+        limit_percentage: u32,
+        is_active: bool,
+        target_pid: Option<i32>,
+    },
+    Escalation(EscalationRecord),
+    ScheduleChanged { schedule: Option<String> },
+    PowerVariantChanged { variant: Option<String> },
+    MemoryPressure { active: bool },
+    ThermalThrottling { active: bool, temperature_c: Option<f32> },
+}
+
+/// What the hosting instance (window or daemon) must do itself.
+#[derive(Clone, Debug, PartialEq)]
+pub enum InstanceCommand {
+    Stop,
+    Activate(Vec<String>),
+}
+
+/// In the private runtime directory, where nobody else can bind it first.
+pub fn socket_path() -> io::Result<PathBuf> {
+    Ok(runtime_dir()?.join("cpu-limiter.sock"))
+}
+
+/// `XDG_RUNTIME_DIR`, else a directory of our own in the shared temp dir.
+/// That one is created 0700 and refused if anyone else owns it or can get in,
+/// so files in it can't be read or planted by other users.
+pub fn runtime_dir() -> io::Result<PathBuf> {
+    if let Some(dir) = std::env::var_os("XDG_RUNTIME_DIR") {
+        return Ok(PathBuf::from(dir));
+    }
+    let uid = nix::unistd::getuid().as_raw();
+    let dir = std::env::temp_dir().join(format!("cpu-limiter-{}", uid));
+    match std::fs::DirBuilder::new().mode(0o700).create(&dir) {
+        Err(e) if e.kind() != io::ErrorKind::AlreadyExists => return Err(e),
+        _ => {}
+    }
+    let metadata = std::fs::symlink_metadata(&dir)?;
+    if !metadata.is_dir() || metadata.uid() != uid || metadata.mode() & 0o077 != 0 {
+        return Err(io::Error::other(format!("{} is not a private directory of ours", dir.display())));
+    }
+    Ok(dir)
+}
+
+fn peer_uid(stream: &UnixStream) -> io::Result<u32> {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        use nix::sys::socket::{getsockopt, sockopt::PeerCredentials};
+        Ok(getsockopt(stream, PeerCredentials)?.uid())
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    {
+        Ok(nix::unistd::getpeereid(stream)?.0.as_raw())
+    }
+}
+
+type CommandHandler = Arc<dyn Fn(InstanceCommand) + Send + Sync>;
+
+/// Serves control requests for the running instance; removes the socket on drop.
+pub struct ControlServer {
+    path: PathBuf,
+}
+
+impl ControlServer {
+    /// `on_command` runs after the reply to `Stop`/`Activate` has been sent.
+    pub fn start(limiter: Arc<Limiter>, on_command: CommandHandler) -> io::Result<Self> {
+        let path = socket_path()?;
+        // The instance lock is already held, so any existing socket is stale
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path)?;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+        log::info!("control socket listening on {}", path.display());
+
+        let owner = nix::unistd::geteuid().as_raw();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        log::warn!("control socket accept failed: {}", e);
+                        continue;
+                    }
+                };
+                // Only the owner (or root) may drive the limiter
+                match peer_uid(&stream) {
+                    Ok(uid) if uid == owner || uid == 0 => {}
+                    Ok(uid) => {
+                        log::warn!("control socket: rejected connection from uid {}", uid);
+                        continue;
+                    }
+                    Err(e) => {
+                        log::warn!("control socket: no peer credentials: {}", e);
+                        continue;
+                    }
+                }
+                let limiter = limiter.clone();
+                let on_command = on_command.clone();
+                thread::spawn(move || serve(stream, &limiter, &*on_command));
+            }
+        });
+        Ok(Self { path })
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+fn write_line<T: Serialize>(writer: &mut impl Write, body: T) -> io::Result<()> {
+    let mut line = serde_json::to_string(&Message { version: PROTOCOL_VERSION, body })?;
+    line.push('\n');
+    writer.write_all(line.as_bytes())
+}
+
+fn serve(stream: UnixStream, limiter: &Limiter, on_command: &(dyn Fn(InstanceCommand) + Send + Sync)) {
+    let Ok(mut writer) = stream.try_clone() else {
+        return;
+    };
+    for line in BufReader::new(stream).lines() {
+        let Ok(line) = line else {
+            return;
+        };
+        let request = match serde_json::from_str::<Message<Request>>(&line) {
+            Ok(message) if message.version == PROTOCOL_VERSION => Ok(message.body),
+            Ok(message) => Err(format!(
+                "unsupported protocol version {} (expected {})",
+                message.version, PROTOCOL_VERSION
+            )),
+            Err(e) => Err(format!("invalid request: {}", e)),
+        };
+        let response = match &request {
+            Ok(request) => handle(limiter, request),
+            Err(message) => Response::Error { message: message.clone() },
+        };
+        if write_line(&mut writer, response).is_err() {
+            return;
+        }
+        match request {
+            Ok(Request::Subscribe) => return stream_events(limiter, &mut writer),
+            Ok(Request::Stop) => return on_command(InstanceCommand::Stop),
+            Ok(Request::Activate { args }) => on_command(InstanceCommand::Activate(args)),
+            _ => {}
+        }
+    }
+}
+
+fn check_percent(percent: u32, apply: impl FnOnce() -> Result<(), String>) -> Response {
+    if !(1..=100).contains(&percent) {
+        return Response::Error { message: format!("{}% is outside 1-100", percent) };
+    }
+    match apply() {
+        Ok(()) => Response::Ok,
+        Err(message) => Response::Error { message },
+    }
+}
+
+fn handle(limiter: &Limiter, request: &Request) -> Response {
+    match request {
+        &Request::Limit { pid, percent } => check_percent(percent, || {
+            limiter.set_target(pid)?;
+            limiter.set_limit(percent);
+            limiter.toggle(true);
+            Ok(())
+        }),
+        &Request::Global { percent } => check_percent(percent, || {
+            limiter.set_global(percent);
+            limiter.toggle(true);
+            Ok(())
+        }),
+        &Request::Release { pid: Some(pid) } => {
+            if limiter.release(pid) {
+                Response::Ok
+            } else {
+                Response::Error { message: format!("PID {} is not being limited", pid) }
+            }
+        }
+        Request::Release { pid: None } => {
+            limiter.toggle(false);
+            Response::Ok
+        }
+        &Request::SetTarget { pid } => match limiter.set_target(pid) {
+            Ok(()) => Response::Ok,
+            Err(message) => Response::Error { message },
+        },
+        Request::ClearTarget => {
+            limiter.clear_target();
+            Response::Ok
+        }
+        Request::SetTargetNames { names } => {
+            limiter.set_target_names(names.clone());
+            Response::Ok
+        }
+        &Request::SetMode { mode } => {
+            limiter.set_mode(mode);
+            Response::Ok
+        }
+        &Request::SetLimit { percent } => check_percent(percent, || {
+            limiter.set_limit(percent);
+            Ok(())
+        }),
+        &Request::Toggle { active } => {
+            limiter.toggle(active);
+            Response::Ok
+        }
+        Request::Status => Response::Status(snapshot(limiter)),
+        Request::History => Response::History { samples: limiter.history() },
+        Request::Subscribe | Request::Activate { .. } | Request::Stop => Response::Ok,
+    }
+}
+
+fn snapshot(limiter: &Limiter) -> StatusSnapshot {
+    let state = limiter.get_state();
+    StatusSnapshot {
+        mode: state.mode,
+        limit_percentage: state.limit_percentage,
+        is_active: state.is_active,
+        status: limiter.get_status(),
+    }
+}
+
+/// Events between two snapshots; everything counts as new without `previous`.
+fn changes(previous: Option<&StatusSnapshot>, current: &StatusSnapshot) -> Vec<Event> {
+    let mut events = Vec::new();
+    let status = &current.status;
+    let state_changed = previous.is_none_or(|p| {
+        (p.mode, p.limit_percentage, p.is_active, p.status.target_pid)
+            != (current.mode, current.limit_percentage, current.is_active, status.target_pid)
+    });
+    if state_changed {
+        events.push(Event::StateChanged {
+            mode: current.mode,
+            limit_percentage: current.limit_percentage,
+            is_active: current.is_active,
+            target_pid: status.target_pid,
+        });
+    }
+    let Some(previous) = previous else {
+        return events;
+    };
+
+    let seen = previous.status.escalations.last().map_or(SystemTime::UNIX_EPOCH, |r| r.time);
+    events.extend(
+        status
+            .escalations
+            .iter()
+            .filter(|record| record.time > seen)
+            .cloned()
+            .map(Event::Escalation),
+    );
+    if status.active_schedule != previous.status.active_schedule {
+        events.push(Event::ScheduleChanged { schedule: status.active_schedule.clone() });
+    }
+    if status.power_variant != previous.status.power_variant {
+        events.push(Event::PowerVariantChanged { variant: status.power_variant.clone() });
+    }
+    if status.memory_pressure != previous.status.memory_pressure {
+        events.push(Event::MemoryPressure { active: status.memory_pressure });
+    }
+    if status.thermal_throttling != previous.status.thermal_throttling {
+        events.push(Event::ThermalThrottling {
+            active: status.thermal_throttling,
+            temperature_c: status.temperature_c,
+        });
+    }
+    events
+}
+
+fn stream_events(limiter: &Limiter, writer: &mut UnixStream) {
+    let mut previous = None;
+    loop {
+        let current = snapshot(limiter);
+        for event in changes(previous.as_ref(), &current) {
+            if write_line(writer, event).is_err() {
+                return; // Subscriber went away
+            }
+        }
+        previous = Some(current);
+        // An idle limiter has nothing to write, so a failed write can't be relied on
+        if hung_up(writer) {
+            return;
+        }
+        thread::sleep(EVENT_POLL);
+    }
+}
+
+/// Whether a subscriber closed its end. Anything it sends is discarded, the
+/// connection only carries events by then.
+fn hung_up(stream: &UnixStream) -> bool {
+    let mut buf = [0; 256];
+    loop {
+        match socket::recv(stream.as_raw_fd(), &mut buf, MsgFlags::MSG_DONTWAIT) {
+            Ok(0) => return true,
+            Ok(_) | Err(Errno::EINTR) => continue,
+            Err(Errno::EAGAIN) => return false,
+            Err(_) => return true,
+        }
+    }
+}
+
+/// Talks only to an instance run by us or by root, so requests and events
+/// never reach a listener another user put in place.
+fn connect() -> io::Result<Option<UnixStream>> {
+    let stream = match UnixStream::connect(socket_path()?) {
+        Ok(stream) => stream,
+        Err(e) if matches!(e.kind(), io::ErrorKind::NotFound | io::ErrorKind::ConnectionRefused) => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let uid = peer_uid(&stream)?;
+    if uid != nix::unistd::geteuid().as_raw() && uid != 0 {
+        return Err(io::Error::other(format!("the control socket is served by uid {}, not us", uid)));
+    }
+    Ok(Some(stream))
+}
+
+fn read_line<T: for<'de> Deserialize<'de>>(reader: &mut impl BufRead) -> io::Result<Option<T>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    let message: Message<T> = serde_json::from_str(&line).map_err(io::Error::other)?;
+    if message.version != PROTOCOL_VERSION {
+        return Err(io::Error::other(format!("instance speaks protocol version {}", message.version)));
+    }
+    Ok(Some(message.body))
+}
+
+/// Sends `request` to the running instance. `Ok(None)` means nothing is listening.
+pub fn send(request: &Request) -> io::Result<Option<Response>> {
+    let Some(mut stream) = connect()? else {
+        return Ok(None);
+    };
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    write_line(&mut stream, request)?;
+    read_line(&mut BufReader::new(&stream))?
+        .map(Some)
+        .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))
+}
+
+/// Calls `on_event` for every event until the instance exits.
+/// Returns `false` when no instance is running.
+pub fn subscribe(mut on_event: impl FnMut(&Event)) -> io::Result<bool> {
+    let Some(mut stream) = connect()? else {
+        return Ok(false);
+    };
+    write_line(&mut stream, Request::Subscribe)?;
+    let mut reader = BufReader::new(&stream);
+    if let Some(Response::Error { message }) = read_line(&mut reader)? {
+        return Err(io::Error::other(message));
+    }
+    while let Some(event) = read_line::<Event>(&mut reader)? {
+        on_event(&event);
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limiter::EscalationStep;
+
+    #[test]
+    fn test_request_wire_format() {
+        let request = Request::Limit { pid: 1234, percent: 30 };
+        let line = serde_json::to_string(&Message { version: PROTOCOL_VERSION, body: &request }).unwrap();
+        assert_eq!(line, r#"{"version":1,"command":"limit","pid":1234,"percent":30}"#);
+        let parsed: Message<Request> = serde_json::from_str(&line).unwrap();
+        assert_eq!(parsed.body, request);
+
+        let parsed: Message<Request> =
+            serde_json::from_str(r#"{"version":1,"command":"set_mode","mode":"Global"}"#).unwrap();
+        assert_eq!(parsed.body, Request::SetMode { mode: LimiterMode::Global });
+        assert!(serde_json::from_str::<Message<Request>>(r#"{"command":"status"}"#).is_err());
+        assert!(serde_json::from_str::<Message<Request>>(r#"{"version":1,"command":"reboot"}"#).is_err());
+    }
+
+    #[test]
+    fn test_refuses_unsafe_targets() {
+        let limiter = Limiter::new();
+        for pid in [-1, 0, 1, std::process::id() as i32] {
+            assert!(matches!(handle(&limiter, &Request::Limit { pid, percent: 30 }), Response::Error { .. }), "{}", pid);
+            assert!(matches!(handle(&limiter, &Request::SetTarget { pid }), Response::Error { .. }), "{}", pid);
+        }
+        let state = limiter.get_state();
+        assert_eq!((state.target_pid, state.is_active), (None, false));
+    }
+
+    #[test]
+    fn test_changes_between_snapshots() {
+        let before = StatusSnapshot {
+            mode: LimiterMode::Targeted,
+            limit_percentage: 50,
+            is_active: true,
+            status: LimiterStatus::default(),
+        };
+        assert!(matches!(changes(None, &before)[..], [Event::StateChanged { is_active: true, .. }]));
+        assert!(changes(Some(&before), &before).is_empty());
+
+        let mut after = before.clone();
+        after.status.active_schedule = Some("work".to_string());
+        after.status.escalations.push(EscalationRecord {
+            pid: 42,
+            name: "spin".to_string(),
+            step: EscalationStep::Throttled,
+            cpu_usage: 99.0,
+            time: SystemTime::now(),
+            error: None,
+        });
+        let events = changes(Some(&before), &after);
+        assert!(matches!(events[..], [Event::Escalation(_), Event::ScheduleChanged { .. }]));
+        assert!(changes(Some(&after), &after).is_empty());
+    }
+
+    #[test]
+    fn test_subscriber_hang_up() {
+        let (mut client, server) = UnixStream::pair().unwrap();
+        assert!(!hung_up(&server));
+        client.write_all(b"ignored\n").unwrap();
+        assert!(!hung_up(&server));
+        drop(client);
+        assert!(hung_up(&server));
+    }
+}
@@ -0,0 +1,159 @@
+use crate::cli::DaemonOptions;
+use crate::config::{self, AuditSettings, ConfigFile};
+use crate::control::{ControlServer, InstanceCommand};
+use crate::history::Sample;
+use crate::http::{self, HttpServer};
+use crate::limiter::Limiter;
+use crate::notify::{self, Notifier};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::signal::unix::{SignalKind, signal};
+use tokio::sync::Notify;
+
+/// Runs the limiter without window or tray until SIGTERM/SIGINT or `stop`.
+pub fn run(options: DaemonOptions) -> anyhow::Result<()> {
+    let limiter = Arc::new(Limiter::new());
+    limiter.configure_from_env();
+
+    // Command-line options win over the config file at startup
+    let mut config_file = ConfigFile::new(config::default_path());
+    let config = match config_file.poll() {
+        Some(Ok(config)) => Some(config),
+        Some(Err(e)) => {
+            log::warn!("{:#}; using defaults", e);
+            None
+        }
+        None => None,
+    };
+    match config {
+        Some(config) => config.apply(&limiter),
+        None => limiter.set_audit_log(AuditSettings::default().open()),
+    }
+
+    let starts_active = options.pid.is_some()
+        || options.global.is_some()
+        || options.limit.is_some()
+        || !options.names.is_empty()
+        || !options.cgroups.is_empty();
+    if let Some(pid) = options.pid {
+        limiter.set_target(pid).map_err(anyhow::Error::msg)?;
+    }
+    if !options.names.is_empty() {
+        limiter.set_target_names(options.names);
+    }
+    if !options.cgroups.is_empty() {
+        limiter.set_target_cgroups(options.cgroups);
+    }
+    if let Some(limit) = options.limit {
+        limiter.set_limit(limit);
+    }
+    if let Some(limit) = options.global {
+        limiter.set_global(limit);
+    }
+    if starts_active {
+        limiter.toggle(true);
+    }
+    limiter.start_background_task();
+    log::info!("daemon started (pid {}), limiting: {}", std::process::id(), limiter.get_state().is_active);
+
+    let stop_requested = Arc::new(Notify::new());
+    let notify = stop_requested.clone();
+    let on_command = Arc::new(move |command| match command {
+        InstanceCommand::Stop => notify.notify_one(),
+        InstanceCommand::Activate(args) => log::info!("second launch ignored, no window to show: {:?}", args),
+    });
+    let _control = ControlServer::start(limiter.clone(), on_command)
+        .inspect_err(|e| log::warn!("control socket unavailable: {}", e))
+        .ok();
+
+    let _http = match options.http.map(Some).map_or_else(http::address_from_env, Ok)? {
+        Some(addr) => Some(HttpServer::start(limiter.clone(), addr)?),
+        None => None,
+    };
+
+    // Ready once the control socket accepts commands
+    let notifier = Notifier::from_env();
+    if let Some(notifier) = &notifier {
+        notifier.ready(&notify::status_text(&limiter));
+    }
+
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+    runtime.block_on(wait_for_shutdown(&stop_requested, &limiter, &mut config_file, notifier.as_ref()))?;
+
+    log::info!("shutting down, resuming paused processes");
+    if let Some(notifier) = &notifier {
+        notifier.stopping("Resuming paused processes");
+    }
+    limiter.shutdown();
+    Ok(())
+}
+
+/// Also reloads the config file when it is edited, samples the system for
+/// `export` and keeps systemd's status and watchdog up to date.
+async fn wait_for_shutdown(
+    stop_requested: &Notify,
+    limiter: &Limiter,
+    config_file: &mut ConfigFile,
+    notifier: Option<&Notifier>,
+) -> std::io::Result<()> {
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    let mut reload = tokio::time::interval(Duration::from_secs(1));
+    let mut status = notify::status_text(limiter); // As sent with READY=1
+    // Pinged at half the timeout, and only while the worker loop advances,
+    // so a hung limiter gets restarted instead of kept alive by this thread
+    let mut watchdog = notifier
+        .and_then(Notifier::watchdog)
+        .map(|timeout| tokio::time::interval(timeout / 2));
+    let mut last_loops = None;
+    loop {
+        tokio::select! {
+            _ = terminate.recv() => log::info!("received SIGTERM"),
+            _ = interrupt.recv() => log::info!("received SIGINT"),
+            _ = stop_requested.notified() => log::info!("stop requested over the control socket"),
+            _ = tick(&mut watchdog) => {
+                let loops = limiter.loops();
+                if last_loops == Some(loops) {
+                    log::warn!("limiter loop made no progress, skipping the watchdog ping");
+                } else if let Some(notifier) = notifier {
+                    notifier.watchdog_ping();
+                }
+                last_loops = Some(loops);
+                continue;
+            }
+            _ = reload.tick() => {
+                match config_file.poll() {
+                    Some(Ok(config)) => {
+                        log::info!("reloaded {}", config_file.path().display());
+                        config.apply(limiter);
+                    }
+                    Some(Err(e)) => log::warn!("{:#}; keeping current settings", e),
+                    None => {}
+                }
+                let snapshot = limiter.snapshot();
+                if !snapshot.processes.is_empty() {
+                    limiter.record_sample(Sample::from_snapshot(&snapshot));
+                }
+                if let Some(notifier) = notifier {
+                    let text = notify::status_text(limiter);
+                    if text != status {
+                        notifier.status(&text);
+                        status = text;
+                    }
+                }
+                continue;
+            }
+        }
+        return Ok(());
+    }
+}
+
+/// Never completes without a watchdog.
+async fn tick(interval: &mut Option<tokio::time::Interval>) {
+    match interval {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
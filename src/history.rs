@@ -0,0 +1,208 @@
+use crate::audit;
+use crate::sampler::Snapshot;
+use anyhow::bail;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+pub const CAPACITY: usize = 300;
+const PROCESSES_PER_SAMPLE: usize = 10; // Top consumers kept with each sample
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProcessSample {
+    pub pid: i32,
+    pub name: String,
+    pub cpu_percent: f32, // 100 = one core
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Sample {
+    pub time: DateTime<Local>,
+    pub cpu_percent: f32, // Average over all cores
+    pub memory_used: u64,
+    pub processes: Vec<ProcessSample>,
+}
+
+impl Sample {
+    pub fn from_snapshot(snapshot: &Snapshot) -> Self {
+        let processes = snapshot
+            .by_cpu()
+            .into_iter()
+            .filter(|process| process.cpu_usage > 0.0)
+            .take(PROCESSES_PER_SAMPLE)
+            .map(|process| ProcessSample {
+                pid: process.pid,
+                name: process.name.clone(),
+                cpu_percent: process.cpu_usage,
+            })
+            .collect();
+        Self {
+            time: snapshot.time,
+            cpu_percent: snapshot.cpu_percent,
+            memory_used: snapshot.memory_used,
+            processes,
+        }
+    }
+}
+
+/// The most recent `CAPACITY` samples.
+#[derive(Default)]
+pub struct History {
+    samples: VecDeque<Sample>,
+}
+
+impl History {
+    pub fn push(&mut self, sample: Sample) {
+        if self.samples.len() >= CAPACITY {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    pub fn samples(&self) -> Vec<Sample> {
+        self.samples.iter().cloned().collect()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    Json,
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        path.extension()?.to_str()?.parse().ok()
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Csv => "csv",
+            Format::Json => "json",
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            _ => bail!("unknown format '{}', expected csv or json", s),
+        }
+    }
+}
+
+/// `~/cpu-limiter-<date>-<time>.<ext>`, for exports started from the window.
+pub fn default_export_path(format: Format) -> PathBuf {
+    let home = PathBuf::from(std::env::var_os("HOME").unwrap_or_default());
+    home.join(format!("cpu-limiter-{}.{}", Local::now().format("%Y%m%d-%H%M%S"), format.extension()))
+}
+
+#[derive(Serialize)]
+struct Export<'a> {
+    samples: &'a [Sample],
+    actions: Vec<&'a audit::Entry>,
+}
+
+/// Samples plus the limiter actions from the time they cover (all of them
+/// without samples). CSV has one row per system sample, process sample and
+/// action, in time order.
+pub fn export(samples: &[Sample], actions: &[audit::Entry], format: Format) -> anyhow::Result<String> {
+    let since = samples.first().map(|sample| sample.time);
+    let actions: Vec<&audit::Entry> = actions
+        .iter()
+        .filter(|entry| since.is_none_or(|since| entry.time >= since))
+        .collect();
+    if format == Format::Json {
+        return Ok(serde_json::to_string_pretty(&Export { samples, actions })?);
+    }
+
+    let mut rows: Vec<(DateTime<Local>, String)> = Vec::new();
+    for sample in samples {
+        let time = sample.time.to_rfc3339();
+        rows.push((sample.time, format!("{},system,,,{:.1},{},", time, sample.cpu_percent, sample.memory_used)));
+        for process in &sample.processes {
+            rows.push((
+                sample.time,
+                format!("{},process,{},{},{:.1},,", time, process.pid, csv_field(&process.name), process.cpu_percent),
+            ));
+        }
+    }
+    for entry in actions {
+        let mut detail = entry.trigger.describe();
+        if let audit::Outcome::Failed { errno } = &entry.outcome {
+            let _ = write!(detail, "; failed: {}", errno);
+        }
+        let record = entry.signal.as_deref().unwrap_or(entry.action.as_str());
+        rows.push((
+            entry.time,
+            format!("{},{},{},{},,,{}", entry.time.to_rfc3339(), record, entry.pid, csv_field(&entry.name), csv_field(&detail)),
+        ));
+    }
+    rows.sort_by_key(|(time, _)| *time);
+
+    let mut out = String::from("time,record,pid,name,cpu_percent,memory_used_bytes,detail\n");
+    for (_, row) in rows {
+        out.push_str(&row);
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::{Action, Entry, Outcome, Trigger};
+    use crate::limiter::LimiterMode;
+
+    #[test]
+    fn test_export() {
+        let start = audit::parse_time("2026-03-02 14:02").unwrap();
+        let sample = Sample {
+            time: start,
+            cpu_percent: 87.25,
+            memory_used: 4096,
+            processes: vec![ProcessSample { pid: 42, name: "cc1plus, 2".to_string(), cpu_percent: 99.0 }],
+        };
+        let action = |minutes: i64| Entry {
+            time: start + chrono::Duration::minutes(minutes),
+            action: Action::Stop,
+            signal: None,
+            pid: 42,
+            name: "cc1plus".to_string(),
+            user: None,
+            mode: LimiterMode::Targeted,
+            trigger: Trigger::DutyCycle { share_percent: 30 },
+            outcome: Outcome::Ok,
+        };
+        let actions = [action(-5), action(1)];
+
+        let csv = export(std::slice::from_ref(&sample), &actions, Format::Csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 4, "{}", csv);
+        assert!(lines[1].ends_with(",system,,,87.2,4096,"), "{}", lines[1]);
+        assert!(lines[2].ends_with(",process,42,\"cc1plus, 2\",99.0,,"), "{}", lines[2]);
+        assert!(lines[3].ends_with(",stop,42,cc1plus,,,duty cycle 30%"), "{}", lines[3]);
+
+        let json: serde_json::Value = serde_json::from_str(&export(&[sample], &actions, Format::Json).unwrap()).unwrap();
+        assert_eq!(json["samples"][0]["processes"][0]["pid"], 42);
+        assert_eq!(json["actions"].as_array().unwrap().len(), 1);
+
+        assert_eq!(Format::from_path(Path::new("out.JSON")), Some(Format::Json));
+        assert!("xml".parse::<Format>().is_err());
+    }
+}
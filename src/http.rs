@@ -0,0 +1,407 @@
+use crate::limiter::Limiter;
+use crate::metrics;
+use anyhow::{Context, bail};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::io::{self, Read, Write};
+use std::net::SocketAddr;
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+const MAX_HEAD: usize = 16 * 1024;
+const MAX_BODY: usize = 64 * 1024;
+/// How long a client has to send its whole request before we hang up.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Address from `CPU_LIMITER_HTTP` (e.g. `127.0.0.1:7878`); the API is off without it.
+pub fn address_from_env() -> anyhow::Result<Option<SocketAddr>> {
+    std::env::var("CPU_LIMITER_HTTP").ok().map(|addr| parse_address(&addr)).transpose()
+}
+
+pub fn parse_address(addr: &str) -> anyhow::Result<SocketAddr> {
+    let addr: SocketAddr = addr.parse().with_context(|| format!("'{}' is not an address like 127.0.0.1:7878", addr))?;
+    if !addr.ip().is_loopback() {
+        bail!("the HTTP API only binds to localhost, not {}", addr.ip());
+    }
+    Ok(addr)
+}
+
+/// `CPU_LIMITER_METRICS_PUBLIC=1` lets `/metrics` be scraped without the token.
+fn public_metrics_from_env() -> bool {
+    std::env::var("CPU_LIMITER_METRICS_PUBLIC").is_ok_and(|value| value == "1")
+}
+
+struct ApiContext {
+    limiter: Arc<Limiter>,
+    token: String,
+    public_metrics: bool,
+}
+
+/// Localhost REST API; requests need `Authorization: Bearer <token>`. The
+/// Prometheus scrape at `/metrics` names limited processes, so it needs the
+/// token too unless public metrics were opted into.
+///
+/// The token comes from `CPU_LIMITER_HTTP_TOKEN`, or is generated and written
+/// to `cpu-limiter-http.token` in the private runtime directory.
+pub struct HttpServer {
+    token_file: Option<PathBuf>,
+}
+
+impl HttpServer {
+    pub fn start(limiter: Arc<Limiter>, addr: SocketAddr) -> anyhow::Result<Self> {
+        let (token, token_file) = match std::env::var("CPU_LIMITER_HTTP_TOKEN") {
+            Ok(token) if !token.is_empty() => (token, None),
+            _ => {
+                let token = generate_token()?;
+                let path = crate::control::runtime_dir()?.join("cpu-limiter-http.token");
+                write_token(&path, &token).with_context(|| format!("failed to write {}", path.display()))?;
+                (token, Some(path))
+            }
+        };
+
+        let listener = std::net::TcpListener::bind(addr).with_context(|| format!("failed to bind {}", addr))?;
+        listener.set_nonblocking(true)?;
+        match &token_file {
+            Some(path) => log::info!("HTTP API on http://{}, token in {}", addr, path.display()),
+            None => log::info!("HTTP API on http://{}", addr),
+        }
+        let public_metrics = public_metrics_from_env();
+        if public_metrics {
+            log::warn!("/metrics is readable without the token by every local user");
+        }
+
+        let context = Arc::new(ApiContext {
+            limiter,
+            token,
+            public_metrics,
+        });
+        thread::spawn(move || {
+            let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+                Ok(runtime) => runtime,
+                Err(e) => return log::error!("HTTP API stopped: {}", e),
+            };
+            runtime.block_on(async {
+                let listener = match tokio::net::TcpListener::from_std(listener) {
+                    Ok(listener) => listener,
+                    Err(e) => return log::error!("HTTP API stopped: {}", e),
+                };
+                loop {
+                    match listener.accept().await {
+                        Ok((stream, _)) => {
+                            tokio::spawn(serve(stream, context.clone()));
+                        }
+                        Err(e) => log::warn!("HTTP accept failed: {}", e),
+                    }
+                }
+            });
+        });
+        Ok(Self { token_file })
+    }
+}
+
+impl Drop for HttpServer {
+    fn drop(&mut self) {
+        if let Some(path) = &self.token_file {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Creates the file afresh, so nobody else can have it open or have made it,
+/// and never follows a symlink at `path`.
+fn write_token(path: &Path, token: &str) -> io::Result<()> {
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.uid() != nix::unistd::getuid().as_raw() => {
+            return Err(io::Error::other("it belongs to another user"));
+        }
+        Ok(_) => std::fs::remove_file(path)?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(path)?;
+    file.write_all(token.as_bytes())
+}
+
+fn generate_token() -> io::Result<String> {
+    let mut bytes = [0u8; 16];
+    std::fs::File::open("/dev/urandom")?.read_exact(&mut bytes)?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+struct Request {
+    method: String,
+    path: String,
+    authorization: Option<String>,
+    body: Vec<u8>,
+}
+
+async fn read_request(stream: &mut TcpStream) -> anyhow::Result<Request> {
+    let mut buf = Vec::new();
+    let head_end = loop {
+        if let Some(end) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break end;
+        }
+        if buf.len() > MAX_HEAD {
+            bail!("request head too large");
+        }
+        let mut chunk = [0u8; 4096];
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            bail!("connection closed");
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    };
+
+    let head = String::from_utf8_lossy(&buf[..head_end]).to_string();
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+    let method = request_line.next().unwrap_or_default().to_string();
+    let path = request_line.next().unwrap_or_default().to_string();
+
+    let mut content_length = 0;
+    let mut authorization = None;
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        match name.trim().to_ascii_lowercase().as_str() {
+            "content-length" => content_length = value.trim().parse().context("bad Content-Length")?,
+            "authorization" => authorization = Some(value.trim().to_string()),
+            _ => {}
+        }
+    }
+    if content_length > MAX_BODY {
+        bail!("request body too large");
+    }
+
+    let mut body = buf.split_off(head_end + 4);
+    while body.len() < content_length {
+        let mut chunk = vec![0u8; content_length - body.len()];
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            bail!("connection closed");
+        }
+        body.extend_from_slice(&chunk[..n]);
+    }
+    body.truncate(content_length);
+    Ok(Request { method, path, authorization, body })
+}
+
+async fn serve(mut stream: TcpStream, context: Arc<ApiContext>) {
+    // A client that opens a connection and trickles or sends nothing would
+    // otherwise hold it forever; dropping the stream closes it
+    let Ok(request) = tokio::time::timeout(REQUEST_TIMEOUT, read_request(&mut stream)).await else {
+        return;
+    };
+    let (status, content_type, body) = match request {
+        Ok(request)
+            if request.method == "GET" && request.path == "/metrics" && (context.public_metrics || authorized(&context, &request)) =>
+        {
+            let body = metrics::render(&context.limiter, &context.limiter.snapshot());
+            (200, metrics::CONTENT_TYPE, body)
+        }
+        Ok(request) => {
+            let (status, body) = respond(&context, &request);
+            (status, "application/json", body.to_string())
+        }
+        Err(e) => (400, "application/json", json!({ "error": e.to_string() }).to_string()),
+    };
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Error",
+    };
+    let response = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason,
+        content_type,
+        body.len(),
+        body
+    );
+    let _ = stream.write_all(response.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
+
+/// Compares without bailing out at the first differing byte.
+fn token_matches(expected: &str, given: &str) -> bool {
+    expected.len() == given.len() && expected.bytes().zip(given.bytes()).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+#[derive(Deserialize)]
+struct PidBody {
+    pid: i32,
+}
+
+#[derive(Deserialize)]
+struct PercentBody {
+    percent: u32,
+}
+
+#[derive(Deserialize)]
+struct ToggleBody {
+    active: bool,
+}
+
+fn parse_body<T: for<'de> Deserialize<'de>>(request: &Request) -> Result<T, (u16, Value)> {
+    serde_json::from_slice(&request.body).map_err(|e| (400, json!({ "error": format!("invalid body: {}", e) })))
+}
+
+fn parse_percent(request: &Request) -> Result<u32, (u16, Value)> {
+    let PercentBody { percent } = parse_body(request)?;
+    if !(1..=100).contains(&percent) {
+        return Err((400, json!({ "error": format!("{}% is outside 1-100", percent) })));
+    }
+    Ok(percent)
+}
+
+fn authorized(context: &ApiContext, request: &Request) -> bool {
+    request
+        .authorization
+        .as_deref()
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|token| token_matches(&context.token, token))
+}
+
+fn respond(context: &ApiContext, request: &Request) -> (u16, Value) {
+    if !authorized(context, request) {
+        return (401, json!({ "error": "missing or wrong bearer token" }));
+    }
+    match route(context, request) {
+        Ok(body) => (200, body),
+        Err(error) => error,
+    }
+}
+
+fn route(context: &ApiContext, request: &Request) -> Result<Value, (u16, Value)> {
+    let limiter = &context.limiter;
+    let path = request.path.split('?').next().unwrap_or_default();
+    let ok = json!({ "ok": true });
+    match (request.method.as_str(), path) {
+        ("GET", "/api/v1/status") => Ok(json!({
+            "state": limiter.get_state(),
+            "status": limiter.get_status(),
+        })),
+        ("GET", "/api/v1/processes") => {
+            let mut processes: Vec<Value> = limiter
+                .snapshot()
+                .processes
+                .values()
+                .map(|process| {
+                    json!({
+                        "pid": process.pid,
+                        "name": process.name,
+                        "cpu_usage": process.cpu_usage,
+                        "memory_bytes": process.memory,
+                    })
+                })
+                .collect();
+            processes.sort_by_key(|p| p["pid"].as_u64());
+            Ok(Value::Array(processes))
+        }
+        ("PUT" | "POST", "/api/v1/target") => {
+            let PidBody { pid } = parse_body(request)?;
+            limiter.set_target(pid).map_err(|message| (400, json!({ "error": message })))?;
+            Ok(ok)
+        }
+        ("DELETE", "/api/v1/target") => {
+            limiter.clear_target();
+            Ok(ok)
+        }
+        ("PUT" | "POST", "/api/v1/global") => {
+            limiter.set_global(parse_percent(request)?);
+            Ok(ok)
+        }
+        ("PUT" | "POST", "/api/v1/limit") => {
+            limiter.set_limit(parse_percent(request)?);
+            Ok(ok)
+        }
+        ("PUT" | "POST", "/api/v1/toggle") => {
+            let ToggleBody { active } = parse_body(request)?;
+            limiter.toggle(active);
+            Ok(ok)
+        }
+        (_, "/api/v1/status" | "/api/v1/processes" | "/api/v1/target" | "/api/v1/global" | "/api/v1/limit" | "/api/v1/toggle") => {
+            Err((405, json!({ "error": format!("{} not allowed on {}", request.method, path) })))
+        }
+        _ => Err((404, json!({ "error": format!("no route for {}", path) }))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    fn request(method: &str, path: &str, token: &str, body: &str) -> Request {
+        Request {
+            method: method.to_string(),
+            path: path.to_string(),
+            authorization: Some(format!("Bearer {}", token)),
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_routes_and_auth() {
+        let context = ApiContext {
+            limiter: Arc::new(Limiter::new()),
+            token: "secret".to_string(),
+            public_metrics: false,
+        };
+
+        assert_eq!(respond(&context, &request("GET", "/api/v1/status", "wrong", "")).0, 401);
+        assert_eq!(respond(&context, &request("GET", "/metrics", "wrong", "")).0, 401);
+        assert!(authorized(&context, &request("GET", "/metrics", "secret", "")));
+        assert_eq!(respond(&context, &request("POST", "/api/v1/global", "secret", r#"{"percent":80}"#)).0, 200);
+        assert_eq!(respond(&context, &request("POST", "/api/v1/toggle", "secret", r#"{"active":true}"#)).0, 200);
+        assert_eq!(respond(&context, &request("POST", "/api/v1/limit", "secret", r#"{"percent":0}"#)).0, 400);
+        for pid in ["-1", "0", "1"] {
+            let body = format!(r#"{{"pid":{}}}"#, pid);
+            assert_eq!(respond(&context, &request("POST", "/api/v1/target", "secret", &body)).0, 400);
+        }
+        assert_eq!(respond(&context, &request("GET", "/api/v1/toggle", "secret", "")).0, 405);
+        assert_eq!(respond(&context, &request("GET", "/api/v2/status", "secret", "")).0, 404);
+
+        let (status, body) = respond(&context, &request("GET", "/api/v1/status?pretty", "secret", ""));
+        assert_eq!(status, 200);
+        assert_eq!(body["state"]["mode"], "Global");
+        assert_eq!(body["state"]["limit_percentage"], 80);
+        assert_eq!(body["state"]["is_active"], true);
+
+        assert!(parse_address("127.0.0.1:7878").is_ok());
+        assert!(parse_address("[::1]:7878").is_ok());
+        assert!(parse_address("0.0.0.0:7878").is_err());
+    }
+
+    #[test]
+    fn test_token_file_replaces_planted_link() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempDir::new("http-token");
+        let target = dir.join("target");
+        std::fs::write(&target, "untouched").unwrap();
+        let path = dir.join("cpu-limiter-http.token");
+        std::os::unix::fs::symlink(&target, &path).unwrap();
+
+        write_token(&path, "secret").unwrap();
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "untouched");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "secret");
+        let metadata = std::fs::symlink_metadata(&path).unwrap();
+        assert!(metadata.is_file());
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+    }
+}
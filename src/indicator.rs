@@ -0,0 +1,159 @@
+use crate::limiter::LimiterStatus;
+use anyhow::bail;
+use tray_icon::Icon;
+
+pub const SIZE: u32 = 32; // Pixels, and samples in the graph
+const BADGE_RADIUS: f32 = 6.5;
+
+/// What the badge in the icon's corner shows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Activity {
+    Idle,       // Limiter off
+    Monitoring, // On, nothing held back right now
+    Limiting,   // Processes stopped or duty-cycled
+}
+
+impl Activity {
+    pub fn new(active: bool, status: &LimiterStatus) -> Self {
+        match (active, status.is_actively_limiting) {
+            (false, _) => Activity::Idle,
+            (true, false) => Activity::Monitoring,
+            (true, true) => Activity::Limiting,
+        }
+    }
+
+    fn color(self) -> [u8; 4] {
+        match self {
+            Activity::Idle => [140, 140, 150, 255],
+            Activity::Monitoring => [0, 212, 255, 255],
+            Activity::Limiting => [255, 152, 0, 255],
+        }
+    }
+}
+
+/// The embedded `tray.png`, a template icon for macOS.
+pub fn static_icon() -> Icon {
+    let image = image::load_from_memory(include_bytes!("tray.png"))
+        .expect("Failed to load icon")
+        .into_rgba8();
+    let (width, height) = image.dimensions();
+    Icon::from_rgba(image.into_raw(), width, height).expect("Failed to create icon from RGBA")
+}
+
+/// From a frame made by `render`.
+pub fn dynamic_icon(rgba: Vec<u8>) -> Option<Icon> {
+    Icon::from_rgba(rgba, SIZE, SIZE).ok()
+}
+
+/// RGBA for a `SIZE`x`SIZE` graph of the last `SIZE` CPU samples (percent,
+/// newest last), colored by load, with the activity badge bottom right.
+pub fn render(history: &[f32], activity: Activity) -> Vec<u8> {
+    let size = SIZE as usize;
+    let mut rgba = vec![0u8; size * size * 4];
+    let mut put = |x: usize, y: usize, color: [u8; 4]| {
+        let offset = (y * size + x) * 4;
+        rgba[offset..offset + 4].copy_from_slice(&color);
+    };
+
+    // Dark backdrop so the graph reads on light and dark panels alike
+    for y in 0..size {
+        for x in 0..size {
+            put(x, y, [30, 30, 40, 200]);
+        }
+    }
+
+    let samples = &history[history.len().saturating_sub(size)..];
+    let start = size - samples.len();
+    for (index, &cpu) in samples.iter().enumerate() {
+        let cpu = cpu.clamp(0.0, 100.0);
+        let color = if cpu > 80.0 {
+            [239, 68, 68, 255]
+        } else if cpu > 50.0 {
+            [250, 204, 21, 255]
+        } else {
+            [0, 230, 118, 255]
+        };
+        let height = (cpu / 100.0 * size as f32).round() as usize;
+        for y in size - height..size {
+            put(start + index, y, color);
+        }
+    }
+
+    let center = size as f32 - BADGE_RADIUS - 0.5;
+    for y in 0..size {
+        for x in 0..size {
+            let distance = ((x as f32 - center).powi(2) + (y as f32 - center).powi(2)).sqrt();
+            if distance <= BADGE_RADIUS - 1.5 {
+                put(x, y, activity.color());
+            } else if distance <= BADGE_RADIUS {
+                put(x, y, [20, 20, 20, 255]);
+            }
+        }
+    }
+    rgba
+}
+
+/// What `{cpu}`, `{memory}`, `{paused}` and `{profile}` stand for.
+pub struct Values<'a> {
+    pub cpu: f32,    // Percent of all cores
+    pub memory: f32, // Percent used
+    pub paused: usize,
+    pub profile: Option<&'a str>,
+}
+
+const PLACEHOLDERS: [&str; 4] = ["cpu", "memory", "paused", "profile"];
+
+/// Fills in a title or tooltip template; lines left empty are dropped.
+pub fn format(template: &str, values: &Values) -> String {
+    let text = template
+        .replace("{cpu}", &format!("{:.0}", values.cpu))
+        .replace("{memory}", &format!("{:.0}", values.memory))
+        .replace("{paused}", &values.paused.to_string())
+        .replace("{profile}", values.profile.unwrap_or(""));
+    text.lines().map(str::trim).filter(|line| !line.is_empty()).collect::<Vec<_>>().join("\n")
+}
+
+pub fn check_template(template: &str) -> anyhow::Result<()> {
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        let Some(close) = rest[open..].find('}') else {
+            bail!("unclosed '{{' in \"{}\"", template);
+        };
+        let name = &rest[open + 1..open + close];
+        if !PLACEHOLDERS.contains(&name) {
+            bail!("unknown placeholder {{{}}}, expected one of {{{}}}", name, PLACEHOLDERS.join("}, {"));
+        }
+        rest = &rest[open + close + 1..];
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pixel(rgba: &[u8], x: usize, y: usize) -> [u8; 4] {
+        let offset = (y * SIZE as usize + x) * 4;
+        rgba[offset..offset + 4].try_into().unwrap()
+    }
+
+    #[test]
+    fn test_render_and_templates() {
+        let rgba = render(&[100.0, 10.0], Activity::Limiting);
+        assert_eq!(rgba.len(), (SIZE * SIZE * 4) as usize);
+        // Samples are right-aligned: the full-load one sits next to the newest
+        assert_eq!(pixel(&rgba, 30, 0), [239, 68, 68, 255]);
+        assert_eq!(pixel(&rgba, 30, 20), [239, 68, 68, 255]);
+        assert_eq!(pixel(&rgba, 29, 0), [30, 30, 40, 200]);
+        assert_eq!(pixel(&rgba, 0, 31), [30, 30, 40, 200]);
+        assert_eq!(pixel(&rgba, 25, 25), Activity::Limiting.color());
+        assert_eq!(pixel(&render(&[], Activity::Idle), 25, 25), Activity::Idle.color());
+
+        let values = Values { cpu: 42.4, memory: 61.6, paused: 2, profile: None };
+        assert_eq!(format("{cpu}% • {paused} paused", &values), "42% • 2 paused");
+        assert_eq!(format("CPU Limiter\n{profile}\nRAM {memory}%", &values), "CPU Limiter\nRAM 62%");
+        assert!(check_template("{cpu}% {memory}% {paused} {profile}").is_ok());
+        assert!(check_template("{load}").is_err());
+        assert!(check_template("{cpu").is_err());
+    }
+}
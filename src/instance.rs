@@ -0,0 +1,132 @@
+use crate::control::{self, Request};
+use anyhow::bail;
+use nix::errno::Errno;
+use nix::fcntl::{Flock, FlockArg};
+use nix::sys::signal::{Signal, kill};
+use nix::unistd::Pid;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+const TAKEOVER_TIMEOUT: Duration = Duration::from_secs(5); // Per step: stop request, then SIGTERM
+
+/// In the private runtime directory, so one instance per user.
+pub fn lock_path() -> io::Result<PathBuf> {
+    Ok(control::runtime_dir()?.join("cpu-limiter.lock"))
+}
+
+/// PID written by the holder. Only informative: the lock decides who runs.
+pub fn owner(path: &Path) -> Option<i32> {
+    std::fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+/// Advisory `flock` held for the life of the process; the kernel releases it
+/// on exit, crashes included, so a stale file never blocks a new start.
+pub struct InstanceLock {
+    _file: Flock<File>,
+}
+
+impl InstanceLock {
+    /// With `force`, a running instance is asked to stop over the control
+    /// socket, then sent SIGTERM if it still holds the lock.
+    pub fn acquire(path: &Path, force: bool) -> anyhow::Result<Self> {
+        if let Some(lock) = Self::try_acquire(path)? {
+            return Ok(lock);
+        }
+        let owner = owner(path);
+        let running = match owner {
+            Some(pid) => format!("CPU Limiter is already running (pid {})", pid),
+            None => "CPU Limiter is already running".to_string(),
+        };
+        if !force {
+            bail!("{}; use --force to take over", running);
+        }
+
+        log::info!("{}, asking it to stop", running);
+        let _ = control::send(&Request::Stop);
+        if let Some(lock) = Self::wait(path)? {
+            return Ok(lock);
+        }
+        if let Some(pid) = owner {
+            log::warn!("pid {} did not stop, sending SIGTERM", pid);
+            kill(Pid::from_raw(pid), Signal::SIGTERM)?;
+            if let Some(lock) = Self::wait(path)? {
+                return Ok(lock);
+            }
+        }
+        bail!("{} and did not stop", running)
+    }
+
+    fn try_acquire(path: &Path) -> io::Result<Option<Self>> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        // Never truncated before locking, or the holder's PID would be lost
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .mode(0o600)
+            .custom_flags(libc::O_NOFOLLOW)
+            .open(path)?;
+        // Someone else's file would let them hold the lock or read what we write
+        if file.metadata()?.uid() != nix::unistd::getuid().as_raw() {
+            return Err(io::Error::other(format!("{} belongs to another user", path.display())));
+        }
+        match Flock::lock(file, FlockArg::LockExclusiveNonblock) {
+            Ok(file) => {
+                file.set_len(0)?;
+                writeln!(&*file, "{}", std::process::id())?;
+                Ok(Some(Self { _file: file }))
+            }
+            Err((_, Errno::EWOULDBLOCK)) => Ok(None),
+            Err((_, errno)) => Err(errno.into()),
+        }
+    }
+
+    fn wait(path: &Path) -> io::Result<Option<Self>> {
+        let deadline = Instant::now() + TAKEOVER_TIMEOUT;
+        while Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(100));
+            if let Some(lock) = Self::try_acquire(path)? {
+                return Ok(Some(lock));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn test_lock_reports_owner() {
+        let dir = TempDir::new("instance");
+        let path = dir.join("cpu-limiter.lock");
+
+        let lock = InstanceLock::acquire(&path, false).unwrap();
+        assert_eq!(owner(&path), Some(std::process::id() as i32));
+        // flock conflicts between separate opens, even within one process
+        assert!(InstanceLock::try_acquire(&path).unwrap().is_none());
+        let error = InstanceLock::acquire(&path, false).err().unwrap().to_string();
+        assert!(error.contains(&format!("pid {}", std::process::id())), "{}", error);
+
+        // A leftover file from a dead holder does not block
+        drop(lock);
+        assert!(path.exists());
+        assert!(InstanceLock::acquire(&path, false).is_ok());
+
+        // A planted link is not followed onto the file it points at
+        let other = dir.join("victim");
+        std::fs::write(&other, "keep").unwrap();
+        let link = dir.join("link.lock");
+        std::os::unix::fs::symlink(&other, &link).unwrap();
+        assert!(InstanceLock::acquire(&link, false).is_err());
+        assert_eq!(std::fs::read_to_string(&other).unwrap(), "keep");
+    }
+}
@@ -1,42 +1,326 @@
+use crate::audit::{Action, AuditLog, Entry, Trigger};
+use crate::cgroup;
+#[cfg(target_os = "linux")]
+use crate::connector::{self, Event};
+use crate::history::{History, Sample};
+use crate::power::{self, PowerState, PowerVariants};
+use crate::sampler::{Process, Sampler, Snapshot};
+use crate::schedule::{self, Schedule};
+use crate::thermal::{self, ThermalTrigger};
+use nix::errno::Errno;
 use nix::sys::signal::{Signal, kill};
 use nix::unistd::Pid;
 use parking_lot::Mutex;
-use std::collections::{HashSet, VecDeque};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::sync::{
     Arc,
-    atomic::{AtomicBool, Ordering},
+    atomic::{AtomicBool, AtomicU64, Ordering},
 };
 use std::thread;
-use std::time::Duration;
-use sysinfo::System;
+use std::time::{Duration, Instant, SystemTime};
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct LimiterState {
     pub target_pid: Option<i32>,
+    pub target_names: Vec<String>, // Limited alongside target_pid
+    pub target_cgroups: Vec<String>, // Every process in these cgroups, too
     pub limit_percentage: u32, // 1-100
     pub mode: LimiterMode,
     pub is_active: bool,
+    pub escalation: Option<EscalationPolicy>,
+    pub memory_rules: Vec<MemoryRule>,
+    pub protected: Vec<String>, // Never signaled by escalation or memory rules
+    pub include_children: bool, // Also limit descendants of target_pid
+    pub check_interval: Duration, // Escalation, memory, thermal and name checks
+    pub schedules: Vec<Schedule>,
+    pub power: PowerVariants,
+    pub power_supply_root: PathBuf,
+    pub thermal: Option<ThermalTrigger>,
+    pub thermal_root: PathBuf,
 }
 
-#[derive(Clone, Debug, Default)]
+/// What gets limited and how; an active schedule swaps it as a whole.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LimiterConfig {
+    pub mode: LimiterMode,
+    pub limit_percentage: u32,
+    pub target_pid: Option<i32>,
+    pub target_names: Vec<String>,
+    #[serde(default)]
+    pub target_cgroups: Vec<String>,
+    pub escalation: Option<EscalationPolicy>,
+    pub memory_rules: Vec<MemoryRule>,
+    pub power: PowerVariants,
+    pub thermal: Option<ThermalTrigger>,
+}
+
+impl LimiterState {
+    pub fn config(&self) -> LimiterConfig {
+        LimiterConfig {
+            mode: self.mode,
+            limit_percentage: self.limit_percentage,
+            target_pid: self.target_pid,
+            target_names: self.target_names.clone(),
+            target_cgroups: self.target_cgroups.clone(),
+            escalation: self.escalation.clone(),
+            memory_rules: self.memory_rules.clone(),
+            power: self.power,
+            thermal: self.thermal.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct LimiterStatus {
     pub currently_paused_pids: Vec<i32>,
     pub target_pid: Option<i32>,
     pub is_actively_limiting: bool,
     pub pause_count: u64,
     pub last_action_time: Option<std::time::SystemTime>,
+    pub escalations: Vec<EscalationRecord>, // Most recent last
+    pub memory_pressure: bool,
+    pub active_schedule: Option<String>,
+    pub power: Option<PowerState>,
+    pub power_variant: Option<String>, // Which power override is applied
+    pub temperature_c: Option<f32>,
+    pub thermal_throttling: bool,
+    pub throttled_ms: u64, // Summed over processes held stopped by the duty cycle
+    pub throttled_wall_ms: u64, // Time with any process held stopped by it
+    pub duty_cycle: Vec<(i32, u32)>, // (pid, share percent) in the last period
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub enum LimiterMode {
     Targeted, // Limit specific PID
     Global,   // Keep total system CPU below limit
 }
 
+/// Escalation for runaway processes, applied on top of the current mode.
+///
+/// A process above `threshold_percent` (per-core, as in the process list) for
+/// `sustain_secs` is duty-cycled at `throttle_percent`. If it still wants more
+/// than the threshold `escalate_after_secs` later, it receives `signal`, and
+/// SIGKILL once `grace_secs` have passed. Protected names are throttled but
+/// never signaled.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EscalationPolicy {
+    pub threshold_percent: f32,
+    pub sustain_secs: u64,
+    pub throttle_percent: u32,
+    pub escalate_after_secs: u64,
+    #[serde(with = "signal_name")]
+    pub signal: Signal,
+    pub grace_secs: u64,
+}
+
+impl Default for EscalationPolicy {
+    fn default() -> Self {
+        Self {
+            threshold_percent: 90.0,
+            sustain_secs: 5 * 60,
+            throttle_percent: 25,
+            escalate_after_secs: 10 * 60,
+            signal: Signal::SIGTERM,
+            grace_secs: 10,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum EscalationStep {
+    Throttled,
+    Released,
+    Protected,
+    Signaled(#[serde(with = "signal_name")] Signal),
+    Killed,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EscalationRecord {
+    pub pid: i32,
+    pub name: String,
+    pub step: EscalationStep,
+    pub cpu_usage: f32,
+    pub time: SystemTime,
+    pub error: Option<String>,
+}
+
+const MAX_ESCALATION_RECORDS: usize = 50;
+
+/// Failed signals by errno name, across every limiter in the process.
+static SIGNAL_ERRORS: Mutex<BTreeMap<String, u64>> = Mutex::new(BTreeMap::new());
+
+fn send_signal(pid: i32, signal: Signal) -> nix::Result<()> {
+    kill(Pid::from_raw(pid), signal).inspect_err(|errno| {
+        *SIGNAL_ERRORS.lock().entry(format!("{:?}", errno)).or_default() += 1;
+    })
+}
+
+pub fn signal_errors() -> BTreeMap<String, u64> {
+    SIGNAL_ERRORS.lock().clone()
+}
+
+/// A signal by name, with or without the "SIG" prefix, or by number.
+pub fn parse_signal(text: &str) -> Option<Signal> {
+    let text = text.trim().to_ascii_uppercase();
+    if let Ok(number) = text.parse::<i32>() {
+        return Signal::try_from(number).ok();
+    }
+    let name = if text.starts_with("SIG") { text } else { format!("SIG{}", text) };
+    Signal::from_str(&name).ok()
+}
+
+/// Signals travel as their names ("SIGTERM") in serialized status.
+mod signal_name {
+    use nix::sys::signal::Signal;
+    use serde::{Deserialize, Deserializer, Serializer, de::Error};
+
+    pub fn serialize<S: Serializer>(signal: &Signal, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(signal.as_str())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Signal, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        super::parse_signal(&name).ok_or_else(|| D::Error::custom(format!("unknown signal '{}'", name)))
+    }
+}
+
+/// What a memory rule watches.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MemoryTrigger {
+    ProcessRss { bytes: u64 },     // Any process above this RSS
+    AvailableBelow { bytes: u64 }, // System available memory below this
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MemoryAction {
+    Pause,        // SIGSTOP until memory recovers
+    Deprioritize, // Nice 19 until memory recovers
+    Terminate,    // SIGTERM
+}
+
+/// RSS rules act on every offender; available-memory rules act on the largest
+/// process, one at a time, until memory recovers. Processes we may not signal
+/// are left alone.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemoryRule {
+    pub trigger: MemoryTrigger,
+    pub action: MemoryAction,
+}
+
+fn is_protected(protected: &[String], name: &str) -> bool {
+    protected.iter().any(|p| p.eq_ignore_ascii_case(name))
+}
+
+/// Why `pid` may not be paused by hand: init and the limiter itself, protected
+/// names, and other users' processes unless we are root.
+fn manual_pause_refusal(pid: i32, name: &str, uid: Option<u32>, protected: &[String]) -> Option<&'static str> {
+    let euid = nix::unistd::geteuid().as_raw();
+    if pid < 1 {
+        Some("it stands for a whole group of processes")
+    } else if pid == 1 || pid == std::process::id() as i32 {
+        Some("it is init or the limiter itself")
+    } else if is_protected(protected, name) {
+        Some("it is protected")
+    } else if euid != 0 && uid.is_some_and(|uid| uid != euid) {
+        Some("it belongs to another user")
+    } else {
+        None
+    }
+}
+
+/// Whether memory rules may act on `process` at all. Protected names are
+/// spared every action: pausing or renicing the display server freezes the
+/// session as surely as terminating it.
+fn memory_eligible(process: &Process, protected: &[String], euid: u32, myself: i32) -> bool {
+    process.pid > 1
+        && process.pid != myself
+        && (euid == 0 || process.uid.is_none_or(|uid| uid == euid))
+        && !is_protected(protected, &process.name)
+}
+
+/// Where a process is in the escalation ladder.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum EscalationStage {
+    Watching { over_since: Instant },
+    Throttled { since: Instant },
+    Protected,
+    Signaled { at: Instant },
+    Killed,
+}
+
+impl EscalationStage {
+    fn is_throttled(self) -> bool {
+        matches!(self, EscalationStage::Throttled { .. } | EscalationStage::Protected)
+    }
+
+    /// Advances one sample. `over` tells whether the process still wants more
+    /// than the threshold; `None` means the process no longer needs tracking.
+    fn advance(
+        self,
+        now: Instant,
+        over: bool,
+        protected: bool,
+        policy: &EscalationPolicy,
+    ) -> (Option<Self>, Option<EscalationStep>) {
+        match self {
+            EscalationStage::Watching { over_since } => {
+                if !over {
+                    (None, None)
+                } else if now.duration_since(over_since) >= Duration::from_secs(policy.sustain_secs) {
+                    (Some(EscalationStage::Throttled { since: now }), Some(EscalationStep::Throttled))
+                } else {
+                    (Some(self), None)
+                }
+            }
+            EscalationStage::Throttled { since } => {
+                if !over {
+                    (None, Some(EscalationStep::Released))
+                } else if now.duration_since(since) >= Duration::from_secs(policy.escalate_after_secs) {
+                    if protected {
+                        (Some(EscalationStage::Protected), Some(EscalationStep::Protected))
+                    } else {
+                        (Some(EscalationStage::Signaled { at: now }), Some(EscalationStep::Signaled(policy.signal)))
+                    }
+                } else {
+                    (Some(self), None)
+                }
+            }
+            EscalationStage::Protected => {
+                if over {
+                    (Some(self), None)
+                } else {
+                    (None, Some(EscalationStep::Released))
+                }
+            }
+            EscalationStage::Signaled { at } => {
+                if now.duration_since(at) < Duration::from_secs(policy.grace_secs) {
+                    (Some(self), None)
+                } else if policy.signal == Signal::SIGKILL {
+                    (Some(EscalationStage::Killed), None)
+                } else {
+                    (Some(EscalationStage::Killed), Some(EscalationStep::Killed))
+                }
+            }
+            EscalationStage::Killed => (Some(self), None),
+        }
+    }
+}
+
 pub struct Limiter {
     state: Arc<Mutex<LimiterState>>,
     stop_signal: Arc<AtomicBool>,
+    loops: Arc<AtomicU64>, // Worker iterations, to tell a stuck worker from an idle one
+    requests: Arc<Mutex<Vec<ProcessRequest>>>, // Taken by the worker on its next loop
     status: Arc<Mutex<LimiterStatus>>,
+    worker: Mutex<Option<thread::JoinHandle<()>>>,
+    audit: Arc<Mutex<Option<AuditLog>>>,
+    history: Mutex<History>, // Recorded by whoever samples the system
+    sampler: Arc<Sampler>,
 }
 
 impl Limiter {
@@ -44,21 +328,68 @@ impl Limiter {
         Self {
             state: Arc::new(Mutex::new(LimiterState {
                 target_pid: None,
+                target_names: Vec::new(),
+                target_cgroups: Vec::new(),
                 limit_percentage: 100, // No limit by default
                 mode: LimiterMode::Targeted,
                 is_active: false,
+                escalation: None,
+                memory_rules: Vec::new(),
+                protected: Vec::new(),
+                include_children: false,
+                check_interval: DEFAULT_CHECK_INTERVAL,
+                schedules: Vec::new(),
+                power: PowerVariants::default(),
+                power_supply_root: PathBuf::from(power::DEFAULT_POWER_SUPPLY_ROOT),
+                thermal: None,
+                thermal_root: PathBuf::from(thermal::DEFAULT_THERMAL_ROOT),
             })),
             stop_signal: Arc::new(AtomicBool::new(false)),
+            loops: Arc::new(AtomicU64::new(0)),
+            requests: Arc::new(Mutex::new(Vec::new())),
             status: Arc::new(Mutex::new(LimiterStatus::default())),
+            worker: Mutex::new(None),
+            audit: Arc::new(Mutex::new(None)),
+            history: Mutex::new(History::default()),
+            sampler: Arc::new(Sampler::new()),
+        }
+    }
+
+    /// Applies the sysfs and procfs root overrides used for testing on fixture trees.
+    pub fn configure_from_env(&self) {
+        if let Some(root) = std::env::var_os("CPU_LIMITER_POWER_SUPPLY_ROOT") {
+            self.set_power_supply_root(root.into());
+        }
+        if let Some(root) = std::env::var_os("CPU_LIMITER_THERMAL_ROOT") {
+            self.set_thermal_root(root.into());
+        }
+        if let Some(root) = std::env::var_os("CPU_LIMITER_PROC_ROOT") {
+            self.sampler.set_proc_root(root.into());
         }
     }
 
-    pub fn set_target(&self, pid: i32) {
+    /// Refuses the pids `manual_refusal` names: 0 and -1 would signal whole
+    /// process groups, and pid 1 the init system.
+    pub fn set_target(&self, pid: i32) -> Result<(), String> {
+        if let Some(reason) = self.manual_refusal(pid) {
+            return Err(format!("PID {} can't be limited: {}", pid, reason));
+        }
         let mut state = self.state.lock();
         state.target_pid = Some(pid);
         state.mode = LimiterMode::Targeted;
         // Resume previous target if changed?
         // For simplicity, the worker handles cleanup when state changes or on loop.
+        Ok(())
+    }
+
+    pub fn clear_target(&self) {
+        let mut state = self.state.lock();
+        state.target_pid = None;
+    }
+
+    pub fn set_mode(&self, mode: LimiterMode) {
+        let mut state = self.state.lock();
+        state.mode = mode;
     }
 
     pub fn set_global(&self, limit: u32) {
@@ -72,17 +403,145 @@ impl Limiter {
         state.limit_percentage = limit.clamp(1, 100);
     }
 
+    pub fn set_escalation(&self, policy: Option<EscalationPolicy>) {
+        let mut state = self.state.lock();
+        state.escalation = policy;
+    }
+
+    pub fn set_memory_rules(&self, rules: Vec<MemoryRule>) {
+        let mut state = self.state.lock();
+        state.memory_rules = rules;
+    }
+
+    pub fn set_target_names(&self, names: Vec<String>) {
+        let mut state = self.state.lock();
+        state.target_names = names;
+    }
+
+    /// Leaves out the cgroups `cgroup::check_target` refuses.
+    pub fn set_target_cgroups(&self, mut cgroups: Vec<String>) {
+        cgroups.retain(|target| match cgroup::check_target(target) {
+            Ok(()) => true,
+            Err(e) => {
+                log::warn!("{:#}", e);
+                false
+            }
+        });
+        let mut state = self.state.lock();
+        state.target_cgroups = cgroups;
+    }
+
+    pub fn set_schedules(&self, schedules: Vec<Schedule>) {
+        let mut state = self.state.lock();
+        state.schedules = schedules;
+    }
+
+    pub fn set_power_variants(&self, variants: PowerVariants) {
+        let mut state = self.state.lock();
+        state.power = variants;
+    }
+
+    pub fn set_power_supply_root(&self, root: PathBuf) {
+        let mut state = self.state.lock();
+        state.power_supply_root = root;
+    }
+
+    pub fn set_thermal(&self, trigger: Option<ThermalTrigger>) {
+        let mut state = self.state.lock();
+        state.thermal = trigger;
+    }
+
+    pub fn set_thermal_root(&self, root: PathBuf) {
+        let mut state = self.state.lock();
+        state.thermal_root = root;
+    }
+
+    pub fn set_protected(&self, names: Vec<String>) {
+        let mut state = self.state.lock();
+        state.protected = names;
+    }
+
+    pub fn set_check_interval(&self, interval: Duration) {
+        let mut state = self.state.lock();
+        state.check_interval = interval;
+    }
+
+    pub fn set_include_children(&self, include: bool) {
+        let mut state = self.state.lock();
+        state.include_children = include;
+    }
+
+    /// Where the worker records what it does to processes; `None` disables it.
+    pub fn set_audit_log(&self, log: Option<AuditLog>) {
+        *self.audit.lock() = log;
+    }
+
+    pub fn record_sample(&self, sample: Sample) {
+        self.history.lock().push(sample);
+    }
+
+    /// Oldest first, for export.
+    pub fn history(&self) -> Vec<Sample> {
+        self.history.lock().samples()
+    }
+
+    /// The latest system sample, shared with the worker.
+    pub fn snapshot(&self) -> Arc<Snapshot> {
+        self.sampler.latest()
+    }
+
+    /// Calls `listener` on the sampler thread after every new snapshot.
+    pub fn on_sample(&self, listener: impl Fn() + Send + 'static) {
+        self.sampler.subscribe(listener);
+    }
+
+    /// Changes several settings at once, so the worker never sees half of them.
+    pub fn update(&self, change: impl FnOnce(&mut LimiterState)) {
+        change(&mut self.state.lock());
+    }
+
     pub fn toggle(&self, active: bool) {
         let mut state = self.state.lock();
         state.is_active = active;
         if !active {
             // Ensure we resume the process if we stop limiting
             if let Some(pid) = state.target_pid {
-                let _ = kill(Pid::from_raw(pid), Signal::SIGCONT);
+                let _ = send_signal(pid, Signal::SIGCONT);
             }
         }
     }
 
+    /// Stops limiting `pid` if it is the current target.
+    pub fn release(&self, pid: i32) -> bool {
+        let mut state = self.state.lock();
+        if state.target_pid != Some(pid) {
+            return false;
+        }
+        state.target_pid = None;
+        let _ = send_signal(pid, Signal::SIGCONT);
+        true
+    }
+
+    /// Continues a paused process right away. The target is released; anything
+    /// else is left running for `SPARE_DURATION` before it can be paused again.
+    pub fn resume_now(&self, pid: i32) {
+        if !self.release(pid) {
+            self.requests.lock().push(ProcessRequest::Resume(pid));
+        }
+    }
+
+    /// Holds `pid` stopped, active or not, until `resume_now`, shutdown or
+    /// `MANUAL_PAUSE_LIMIT`. Refused for the processes `manual_pause_refusal` names.
+    pub fn pause_now(&self, pid: i32) {
+        self.requests.lock().push(ProcessRequest::Pause(pid));
+    }
+
+    /// Why `pid` may not be paused or limited by hand, if it may not.
+    pub fn manual_refusal(&self, pid: i32) -> Option<&'static str> {
+        let (name, uid) = describe_process(&self.snapshot(), &self.sampler.proc_root(), pid);
+        manual_pause_refusal(pid, &name.unwrap_or_default(), uid, &self.state.lock().protected)
+    }
+
     #[allow(dead_code)]
     pub fn get_state(&self) -> LimiterState {
         self.state.lock().clone()
@@ -93,185 +552,1071 @@ impl Limiter {
     }
 
     pub fn start_background_task(&self) {
-        let state_handle = self.state.clone();
-        let stop_handle = self.stop_signal.clone();
-        let status_handle = self.status.clone();
-
-        thread::spawn(move || {
-            let mut sys = System::new_all();
-            let mut targeted_pid: Option<i32> = None;
-            let mut paused_global: VecDeque<i32> = VecDeque::new();
-            let mut paused_global_set: HashSet<i32> = HashSet::new();
-
-            // Duty cycle period in ms
-            const PERIOD_MS: u64 = 100;
-            const GLOBAL_HYSTERESIS: f32 = 5.0;
-
-            loop {
-                if stop_handle.load(Ordering::Relaxed) {
-                    if let Some(pid) = targeted_pid.take() {
-                        let _ = kill(Pid::from_raw(pid), Signal::SIGCONT);
-                    }
-                    while let Some(pid) = paused_global.pop_front() {
-                        let _ = kill(Pid::from_raw(pid), Signal::SIGCONT);
-                        paused_global_set.remove(&pid);
-                    }
-                    break;
-                }
+        self.sampler.start();
+        let worker = Worker {
+            state: self.state.clone(),
+            stop: self.stop_signal.clone(),
+            loops: self.loops.clone(),
+            requests: self.requests.clone(),
+            status: self.status.clone(),
+            audit: self.audit.clone(),
+            snapshot: self.sampler.latest(),
+            sampler: self.sampler.clone(),
+            #[cfg(target_os = "linux")]
+            events: connector::watch(self.stop_signal.clone()),
+            mode: LimiterMode::Targeted,
+            targeted_pid: None,
+            named_targets: Vec::new(),
+            target_names: Vec::new(),
+            target_cgroups: Vec::new(),
+            cgroups_unreadable_warned: false,
+            last_target_resolve: Instant::now(),
+            duty_pids: HashSet::new(),
+            paused: PausedSet::default(),
+            escalation: HashMap::new(),
+            last_escalation_check: Instant::now(),
+            memory_rules: Vec::new(),
+            memory_holds: HashMap::new(),
+            memory_skipped: HashMap::new(),
+            last_memory_check: Instant::now(),
+            power: None,
+            last_power_check: None,
+            temperature: None,
+            last_thermal_check: None,
+            thermal_throttled: Vec::new(),
+            spared: HashMap::new(),
+            manual_paused: HashMap::new(),
+        };
+
+        *self.worker.lock() = Some(thread::spawn(move || worker.run()));
+    }
+
+    /// Grows while the worker loop runs; a watchdog pings only if it moved.
+    pub fn loops(&self) -> u64 {
+        self.loops.load(Ordering::Relaxed)
+    }
+
+    /// Stops the worker and waits until it has resumed everything it paused.
+    pub fn shutdown(&self) {
+        self.stop_signal.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.worker.lock().take() {
+            let _ = handle.join();
+        }
+        self.sampler.stop();
+    }
+}
 
-                let (target, limit, mode, active) = {
-                    let s = state_handle.lock();
-                    (s.target_pid, s.limit_percentage, s.mode, s.is_active)
+// Duty cycle period in ms
+const PERIOD_MS: u64 = 100;
+const GLOBAL_HYSTERESIS: f32 = 5.0;
+pub const DEFAULT_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+const DEPRIORITIZED_NICE: i32 = 19;
+const POWER_INTERVAL: Duration = Duration::from_secs(5);
+const SPARE_DURATION: Duration = Duration::from_secs(60);
+const MEMORY_COOLDOWN: Duration = Duration::from_secs(30); // Before a rule picks another process
+const MEMORY_PAUSE_LIMIT: Duration = Duration::from_secs(120); // A stopped process never shrinks
+const MANUAL_PAUSE_LIMIT: Duration = Duration::from_secs(15 * 60); // Then resumed, in case it was forgotten
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum PauseReason {
+    Global,
+    Memory,
+    Manual, // `Limiter::pause_now`
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum ProcessRequest {
+    Pause(i32),
+    Resume(i32),
+}
+
+/// Processes held with SIGSTOP outside the duty cycle, oldest first.
+#[derive(Default)]
+struct PausedSet {
+    entries: VecDeque<(i32, PauseReason)>,
+}
+
+impl PausedSet {
+    fn contains(&self, pid: i32) -> bool {
+        self.entries.iter().any(|(p, _)| *p == pid)
+    }
+
+    fn pids(&self) -> impl Iterator<Item = i32> + '_ {
+        self.entries.iter().map(|(pid, _)| *pid)
+    }
+
+    fn insert(&mut self, pid: i32, reason: PauseReason) {
+        self.entries.push_back((pid, reason));
+    }
+
+    fn remove(&mut self, pid: i32) -> bool {
+        let before = self.entries.len();
+        self.entries.retain(|(p, _)| *p != pid);
+        self.entries.len() != before
+    }
+
+    fn take_oldest(&mut self, reason: PauseReason) -> Option<i32> {
+        let index = self.entries.iter().position(|(_, r)| *r == reason)?;
+        self.entries.remove(index).map(|(pid, _)| pid)
+    }
+}
+
+/// A process a memory rule acted on, kept until memory recovers.
+struct MemoryHold {
+    rule: usize,
+    action: MemoryAction,
+    since: Instant,
+    previous_nice: Option<i32>,
+    available: u64, // System available memory when acted on
+}
+
+#[cfg(target_os = "linux")]
+fn process_uid(proc_root: &Path, pid: i32) -> Option<u32> {
+    use std::os::unix::fs::MetadataExt;
+    std::fs::metadata(proc_root.join(pid.to_string())).ok().map(|m| m.uid())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn process_uid(_proc_root: &Path, _pid: i32) -> Option<u32> {
+    None
+}
+
+/// From the snapshot, or from procfs for processes started since the last sample.
+fn describe_process(snapshot: &Snapshot, proc_root: &Path, pid: i32) -> (Option<String>, Option<u32>) {
+    let process = snapshot.process(pid);
+    let name = process.map(|p| p.name.clone()).or_else(|| process_name(proc_root, pid));
+    (name, process.and_then(|p| p.uid).or_else(|| process_uid(proc_root, pid)))
+}
+
+#[cfg(target_os = "linux")]
+fn process_name(proc_root: &Path, pid: i32) -> Option<String> {
+    let path = proc_root.join(pid.to_string()).join("comm");
+    std::fs::read_to_string(path).ok().map(|name| name.trim_end().to_string())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn process_name(_proc_root: &Path, _pid: i32) -> Option<String> {
+    None
+}
+
+/// Sets the nice value of `pid`, returning the previous one.
+fn renice(pid: i32, nice: i32) -> nix::Result<i32> {
+    Errno::clear();
+    // SAFETY: plain syscalls on a pid; errors come back through errno.
+    let previous = unsafe { libc::getpriority(libc::PRIO_PROCESS, pid as libc::id_t) };
+    if previous == -1 && Errno::last_raw() != 0 {
+        return Err(Errno::last());
+    }
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS, pid as libc::id_t, nice) } == -1 {
+        return Err(Errno::last());
+    }
+    Ok(previous)
+}
+
+/// State owned by the background thread.
+struct Worker {
+    state: Arc<Mutex<LimiterState>>,
+    stop: Arc<AtomicBool>,
+    loops: Arc<AtomicU64>,
+    requests: Arc<Mutex<Vec<ProcessRequest>>>,
+    status: Arc<Mutex<LimiterStatus>>,
+    audit: Arc<Mutex<Option<AuditLog>>>,
+    sampler: Arc<Sampler>,
+    snapshot: Arc<Snapshot>, // Taken at the start of each loop
+    #[cfg(target_os = "linux")]
+    events: Arc<Mutex<Vec<Event>>>, // From the proc connector, if it could be subscribed to
+    mode: LimiterMode, // For audit entries
+    targeted_pid: Option<i32>,
+    named_targets: Vec<i32>, // Pids matching target_names or in target_cgroups
+    target_names: Vec<String>,
+    target_cgroups: Vec<String>,
+    cgroups_unreadable_warned: bool,
+    last_target_resolve: Instant,
+    duty_pids: HashSet<i32>, // Stopped at the end of the last period
+    paused: PausedSet,
+    escalation: HashMap<(i32, u64), EscalationStage>, // By pid and start time, so a reused pid starts over
+    last_escalation_check: Instant,
+    memory_rules: Vec<MemoryRule>,
+    memory_holds: HashMap<i32, MemoryHold>,
+    memory_skipped: HashMap<(i32, u64), Option<Instant>>, // By pid and start time: for good, or until then
+    last_memory_check: Instant,
+    power: Option<PowerState>,
+    last_power_check: Option<Instant>,
+    temperature: Option<f32>,
+    last_thermal_check: Option<Instant>,
+    thermal_throttled: Vec<i32>, // Oldest first
+    spared: HashMap<i32, Instant>, // Resumed on request, when
+    manual_paused: HashMap<i32, Instant>, // Paused on request, when
+}
+
+/// Adds `pid` to a duty cycle list, keeping the strictest share.
+fn add_duty(duty: &mut Vec<(i32, u32)>, pid: i32, percent: u32) {
+    match duty.iter_mut().find(|(p, _)| *p == pid) {
+        Some(entry) => entry.1 = entry.1.min(percent),
+        None => duty.push((pid, percent)),
+    }
+}
+
+impl Worker {
+    fn run(mut self) {
+        loop {
+            self.loops.fetch_add(1, Ordering::Relaxed);
+            let snapshot = self.sampler.latest();
+            let fresh = !Arc::ptr_eq(&snapshot, &self.snapshot);
+            self.snapshot = snapshot;
+            if self.stop.load(Ordering::Relaxed) {
+                self.resume_all(PauseReason::Manual);
+                self.release_all();
+                break;
+            }
+
+            let (active, mut config, schedule, protected, include_children, check_interval, power_root, thermal_root) = {
+                let s = self.state.lock();
+                let (config, schedule) = match schedule::active(&s.schedules) {
+                    Some(schedule) => (schedule.config.clone(), Some(schedule.name.clone())),
+                    None => (s.config(), None),
                 };
+                (
+                    s.is_active,
+                    config,
+                    schedule,
+                    s.protected.clone(),
+                    s.include_children,
+                    s.check_interval,
+                    s.power_supply_root.clone(),
+                    s.thermal_root.clone(),
+                )
+            };
+
+            // Temperature is shown in the UI even when no trigger uses it
+            let mut thermal_due = false;
+            if self.last_thermal_check.is_none_or(|t| t.elapsed() >= check_interval) {
+                let zones = config.thermal.as_ref().map_or(&[][..], |t| &t.zones[..]);
+                self.temperature = thermal::read_temperature(&thermal_root, zones);
+                self.status.lock().temperature_c = self.temperature;
+                self.last_thermal_check = Some(Instant::now());
+                thermal_due = true;
+            }
 
-                if !active {
-                    if let Some(pid) = targeted_pid.take() {
-                        let _ = kill(Pid::from_raw(pid), Signal::SIGCONT);
+            let mut power_variant = None;
+            if !config.power.is_empty() {
+                if self.last_power_check.is_none_or(|t| t.elapsed() >= POWER_INTERVAL) {
+                    self.power = power::read_power_state(&power_root);
+                    self.last_power_check = Some(Instant::now());
+                }
+                if let Some((label, variant)) = self.power.and_then(|p| config.power.select(p)) {
+                    config.mode = variant.mode.unwrap_or(config.mode);
+                    config.limit_percentage = variant.limit_percentage.clamp(1, 100);
+                    power_variant = Some(label.to_string());
+                }
+            }
+
+            let LimiterConfig {
+                mode,
+                limit_percentage: limit,
+                target_pid: target,
+                target_names,
+                target_cgroups,
+                escalation,
+                memory_rules,
+                thermal,
+                ..
+            } = config;
+            self.mode = mode;
+
+            self.handle_requests(&protected);
+            let targeting = active && mode == LimiterMode::Targeted;
+            self.handle_process_events(targeting, &target_names, &target_cgroups, &protected, include_children);
+
+            if !active {
+                self.release_all();
+                // Update status when inactive; manual pauses outlive the limiter
+                {
+                    let mut status = self.status.lock();
+                    status.currently_paused_pids = self.paused.pids().collect();
+                    status.duty_cycle.clear();
+                    status.target_pid = None;
+                    status.is_actively_limiting = false;
+                    status.memory_pressure = false;
+                    status.active_schedule = None;
+                    status.power_variant = None;
+                    status.thermal_throttling = false;
+                }
+                thread::sleep(Duration::from_millis(PERIOD_MS));
+                continue;
+            }
+
+            {
+                let mut status = self.status.lock();
+                if status.active_schedule != schedule {
+                    log::info!("schedule: {}", schedule.as_deref().unwrap_or("none"));
+                    status.active_schedule = schedule;
+                }
+                if status.power_variant != power_variant {
+                    log::info!("power variant: {}", power_variant.as_deref().unwrap_or("none"));
+                    status.power_variant = power_variant;
+                }
+                status.power = self.power;
+            }
+
+            match &escalation {
+                Some(policy) => {
+                    if self.last_escalation_check.elapsed() >= check_interval {
+                        self.check_escalation(policy, &protected);
+                        self.last_escalation_check = Instant::now();
                     }
-                    while let Some(pid) = paused_global.pop_front() {
-                        let _ = kill(Pid::from_raw(pid), Signal::SIGCONT);
-                        paused_global_set.remove(&pid);
+                }
+                None => self.release_escalation(),
+            }
+
+            if memory_rules != self.memory_rules {
+                self.release_memory();
+                self.memory_rules = memory_rules;
+            }
+            if !self.memory_rules.is_empty() && self.last_memory_check.elapsed() >= check_interval {
+                self.check_memory(&protected);
+                self.last_memory_check = Instant::now();
+            }
+            match &thermal {
+                Some(trigger) if thermal_due => self.check_thermal(trigger),
+                Some(_) => {}
+                None => self.thermal_throttled.clear(),
+            }
+
+            let throttle_percent = escalation.as_ref().map_or(100, |p| p.throttle_percent);
+            let mut duty: Vec<(i32, u32)> = self
+                .escalation
+                .iter()
+                .filter(|(_, stage)| stage.is_throttled())
+                .map(|((pid, _), _)| (*pid, throttle_percent))
+                .collect();
+            if let Some(trigger) = &thermal {
+                for pid in &self.thermal_throttled {
+                    add_duty(&mut duty, *pid, trigger.throttle_percent);
+                }
+            }
+
+            match mode {
+                LimiterMode::Targeted => {
+                    self.resume_all(PauseReason::Global);
+
+                    if self.targeted_pid != target {
+                        if let Some(pid) = self.targeted_pid.take() {
+                            self.duty_pids.remove(&pid);
+                            let _ = self.signal(pid, Signal::SIGCONT, Trigger::Released);
+                        }
+                        self.targeted_pid = target;
                     }
-                    // Update status when inactive
+
+                    if target_names != self.target_names
+                        || target_cgroups != self.target_cgroups
+                        || self.last_target_resolve.elapsed() >= check_interval
                     {
-                        let mut status = status_handle.lock();
-                        status.currently_paused_pids.clear();
-                        status.target_pid = None;
-                        status.is_actively_limiting = false;
+                        let tree_root = self.targeted_pid.filter(|_| include_children);
+                        self.resolve_named_targets(target_names, target_cgroups, &protected, tree_root);
+                    }
+
+                    for pid in self.targeted_pid.iter().chain(&self.named_targets) {
+                        add_duty(&mut duty, *pid, limit);
                     }
-                    thread::sleep(Duration::from_millis(PERIOD_MS));
-                    continue;
                 }
+                LimiterMode::Global => {
+                    if let Some(pid) = self.targeted_pid.take() {
+                        self.duty_pids.remove(&pid);
+                        let _ = self.signal(pid, Signal::SIGCONT, Trigger::Released);
+                    }
 
-                match mode {
-                    LimiterMode::Targeted => {
-                        if !paused_global.is_empty() {
-                            while let Some(pid) = paused_global.pop_front() {
-                                let _ = kill(Pid::from_raw(pid), Signal::SIGCONT);
-                                paused_global_set.remove(&pid);
-                            }
-                        }
+                    // Each snapshot is acted on once, or one spike would pause several processes
+                    if fresh {
+                        self.global_step(limit);
+                    }
+                }
+            }
 
-                        if targeted_pid != target {
-                            if let Some(pid) = targeted_pid.take() {
-                                let _ = kill(Pid::from_raw(pid), Signal::SIGCONT);
-                            }
-                            targeted_pid = target;
-                        }
+            duty.retain(|(pid, _)| !self.spared.contains_key(pid) && !self.paused.contains(*pid));
+            let gone = self.duty_cycle(&duty);
+            if self.targeted_pid.is_some_and(|pid| gone.contains(&pid)) {
+                self.targeted_pid = None;
+            }
+            self.thermal_throttled.retain(|pid| !gone.contains(pid));
+        }
+    }
 
-                        if let Some(pid) = targeted_pid {
-                            let run_ms = (PERIOD_MS * limit as u64) / 100;
-                            let stop_ms = PERIOD_MS - run_ms;
+    /// Adds the top consumer to the thermal duty cycle while hot, and drops
+    /// the oldest one once the zones cool below the hysteresis band.
+    fn check_thermal(&mut self, trigger: &ThermalTrigger) {
+        let Some(temperature) = self.temperature else {
+            return;
+        };
 
-                            if kill(Pid::from_raw(pid), Signal::SIGCONT).is_err() {
-                                targeted_pid = None;
-                                thread::sleep(Duration::from_millis(PERIOD_MS));
-                                continue;
-                            }
-                            if run_ms > 0 {
-                                thread::sleep(Duration::from_millis(run_ms));
-                            }
+        if temperature > trigger.threshold_c {
+            let myself = std::process::id() as i32;
+            let hottest = self
+                .snapshot
+                .processes
+                .values()
+                .map(|process| (process.pid, process.cpu_usage))
+                .filter(|(pid, usage)| {
+                    *pid > 1
+                        && *pid != myself
+                        && *usage > 0.5
+                        && !self.thermal_throttled.contains(pid)
+                        && !self.paused.contains(*pid)
+                })
+                .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+            if let Some((pid, usage)) = hottest {
+                log::info!("thermal: {:.1}°C, throttling {} at {:.1}% CPU", temperature, pid, usage);
+                self.thermal_throttled.push(pid);
+            }
+        } else if temperature < trigger.threshold_c - trigger.hysteresis_c && !self.thermal_throttled.is_empty() {
+            let pid = self.thermal_throttled.remove(0);
+            log::info!("thermal: {:.1}°C, releasing {}", temperature, pid);
+        }
+        self.status.lock().thermal_throttling = !self.thermal_throttled.is_empty();
+    }
 
-                            if stop_ms > 0 {
-                                if kill(Pid::from_raw(pid), Signal::SIGSTOP).is_err() {
-                                    targeted_pid = None;
-                                    thread::sleep(Duration::from_millis(PERIOD_MS));
-                                    continue;
-                                }
-                                // Update status - process is being paused
-                                {
-                                    let mut status = status_handle.lock();
-                                    status.target_pid = Some(pid);
-                                    status.currently_paused_pids = vec![pid];
-                                    status.is_actively_limiting = true;
-                                    status.pause_count += 1;
-                                    status.last_action_time = Some(std::time::SystemTime::now());
-                                }
-                                thread::sleep(Duration::from_millis(stop_ms));
-                            } else {
-                                // Not pausing, just update target
-                                let mut status = status_handle.lock();
-                                status.target_pid = Some(pid);
-                                status.currently_paused_pids.clear();
-                                status.is_actively_limiting = false;
-                            }
-                        } else {
-                            thread::sleep(Duration::from_millis(PERIOD_MS));
+    /// Pauses the top consumer while the system is over `limit`, and resumes
+    /// the oldest paused process once it drops below the hysteresis band.
+    fn global_step(&mut self, limit: u32) {
+        let total_load = self.snapshot.cpu_percent;
+        let trigger = Trigger::GlobalLoad { load_percent: total_load, limit_percent: limit };
+        let limit_f32 = limit as f32;
+        let lower_threshold = (limit_f32 - GLOBAL_HYSTERESIS).max(0.0);
+
+        if total_load > limit_f32 {
+            let myself = std::process::id() as i32;
+
+            let mut candidates: Vec<_> = self
+                .snapshot
+                .processes
+                .values()
+                .filter_map(|process| {
+                    let pid_i32 = process.pid;
+                    if pid_i32 == myself
+                        || self.paused.contains(pid_i32)
+                        || self.spared.contains_key(&pid_i32)
+                        || self.escalation.get(&(pid_i32, process.start_time)).is_some_and(|s| s.is_throttled())
+                    {
+                        return None;
+                    }
+                    let usage = process.cpu_usage;
+                    if usage <= 0.5 {
+                        return None;
+                    }
+                    Some((pid_i32, usage))
+                })
+                .collect();
+
+            candidates.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+
+            if let Some((pid, _)) = candidates.first()
+                && self.pause(*pid, PauseReason::Global, trigger).is_ok()
+            {
+                let mut status = self.status.lock();
+                status.pause_count += 1;
+                status.last_action_time = Some(SystemTime::now());
+            }
+        } else if total_load < lower_threshold
+            && let Some(pid) = self.paused.take_oldest(PauseReason::Global)
+        {
+            let _ = self.signal(pid, Signal::SIGCONT, trigger);
+        }
+    }
+
+    /// Collects pids matching `names` or in one of `cgroups`, leaving out
+    /// protected cgroup members, plus every descendant of `tree_root`. Processes moved between cgroups are picked
+    /// up once the sampler rereads their membership.
+    fn resolve_named_targets(&mut self, names: Vec<String>, cgroups: Vec<String>, protected: &[String], tree_root: Option<i32>) {
+        // Found through process events since the snapshot was taken
+        let mut newer: Vec<i32> = std::mem::take(&mut self.named_targets);
+        newer.retain(|pid| !self.snapshot.processes.contains_key(pid) && kill(Pid::from_raw(*pid), None).is_ok());
+        // The sysinfo fallback knows no cgroups, so they would match nothing
+        if cgroups != self.target_cgroups {
+            self.cgroups_unreadable_warned = false;
+        }
+        if !cgroups.is_empty()
+            && !self.cgroups_unreadable_warned
+            && !self.snapshot.processes.is_empty()
+            && self.snapshot.processes.values().all(|process| process.cgroup.is_none())
+        {
+            log::warn!("cgroup targets {} are ignored: process cgroups can't be read here", cgroups.join(", "));
+            self.cgroups_unreadable_warned = true;
+        }
+        if !names.is_empty() || !cgroups.is_empty() || tree_root.is_some() {
+            let myself = std::process::id() as i32;
+            self.named_targets = self
+                .snapshot
+                .processes
+                .values()
+                .filter(|process| {
+                    names.iter().any(|n| n.eq_ignore_ascii_case(&process.name))
+                        || (!is_protected(protected, &process.name)
+                            && process
+                                .cgroup
+                                .as_deref()
+                                .is_some_and(|path| cgroups.iter().any(|target| cgroup::contains(target, path))))
+                })
+                .map(|process| process.pid)
+                .filter(|pid| *pid != myself)
+                .collect();
+
+            if let Some(root) = tree_root {
+                let mut parents = vec![root];
+                while let Some(parent) = parents.pop() {
+                    for (&pid, process) in &self.snapshot.processes {
+                        if process.parent == Some(parent)
+                            && pid != myself
+                            && !self.named_targets.contains(&pid)
+                        {
+                            self.named_targets.push(pid);
+                            parents.push(pid);
                         }
                     }
-                    LimiterMode::Global => {
-                        if let Some(pid) = targeted_pid.take() {
-                            let _ = kill(Pid::from_raw(pid), Signal::SIGCONT);
+                }
+            }
+            if names == self.target_names && cgroups == self.target_cgroups {
+                self.named_targets.extend(newer);
+            }
+        }
+        self.target_names = names;
+        self.target_cgroups = cgroups;
+        self.last_target_resolve = Instant::now();
+    }
+
+    /// Runs one period over `pids`, letting each run for its share of the
+    /// period before stopping it. Returns the pids that could not be signaled.
+    fn duty_cycle(&mut self, pids: &[(i32, u32)]) -> Vec<i32> {
+        let start = Instant::now();
+        let mut gone = Vec::new();
+
+        // Anything left stopped by the last period but no longer limited
+        let previous = std::mem::take(&mut self.duty_pids);
+        for &pid in &previous {
+            if !pids.iter().any(|(p, _)| *p == pid) {
+                let _ = self.signal(pid, Signal::SIGCONT, Trigger::Released);
+            }
+        }
+        let mut schedule: Vec<(u64, i32)> = Vec::with_capacity(pids.len());
+
+        // Stops repeat every period, so only entering the cycle and failures are audited
+        for &(pid, limit) in pids {
+            let result = send_signal(pid, Signal::SIGCONT);
+            if result.is_err() {
+                self.audit(Action::Cont, pid, None, Trigger::DutyCycle { share_percent: limit }, &result);
+                gone.push(pid);
+                continue;
+            }
+            schedule.push(((PERIOD_MS * limit as u64) / 100, pid));
+        }
+        schedule.sort_unstable();
+
+        let mut stopped = Vec::new();
+        let mut throttled_ms = 0;
+        let mut throttled_wall_ms = 0;
+        for (run_ms, pid) in schedule {
+            if run_ms >= PERIOD_MS {
+                continue;
+            }
+            let run = Duration::from_millis(run_ms);
+            if let Some(wait) = run.checked_sub(start.elapsed()) {
+                thread::sleep(wait);
+            }
+            let result = send_signal(pid, Signal::SIGSTOP);
+            if result.is_err() || !previous.contains(&pid) {
+                let share_percent = pids.iter().find(|(p, _)| *p == pid).map_or(100, |(_, share)| *share);
+                self.audit(Action::Stop, pid, None, Trigger::DutyCycle { share_percent }, &result);
+            }
+            if result.is_err() {
+                gone.push(pid);
+            } else {
+                stopped.push(pid);
+                self.duty_pids.insert(pid);
+                throttled_ms += PERIOD_MS - run_ms;
+                throttled_wall_ms = throttled_wall_ms.max(PERIOD_MS - run_ms);
+            }
+        }
+
+        // Update status - processes paused for the rest of the period
+        {
+            let mut status = self.status.lock();
+            if !stopped.is_empty() {
+                status.pause_count += stopped.len() as u64;
+                status.last_action_time = Some(SystemTime::now());
+                status.throttled_ms += throttled_ms;
+                status.throttled_wall_ms += throttled_wall_ms;
+            }
+            status.target_pid = self.targeted_pid;
+            status.duty_cycle = pids.iter().filter(|(pid, _)| !gone.contains(pid)).copied().collect();
+            status.currently_paused_pids = stopped;
+            status.currently_paused_pids.extend(self.paused.pids());
+            status.is_actively_limiting = !status.currently_paused_pids.is_empty();
+        }
+
+        if let Some(wait) = Duration::from_millis(PERIOD_MS).checked_sub(start.elapsed()) {
+            thread::sleep(wait);
+        }
+        gone
+    }
+
+    /// Sends `signal` and records it in the audit log.
+    fn signal(&mut self, pid: i32, signal: Signal, trigger: Trigger) -> nix::Result<()> {
+        let result = send_signal(pid, signal);
+        let (action, name) = match signal {
+            Signal::SIGSTOP => (Action::Freeze, None),
+            Signal::SIGCONT => (Action::Cont, None),
+            _ => (Action::Signal, Some(signal)),
+        };
+        self.audit(action, pid, name, trigger, &result);
+        result
+    }
+
+    fn audit<T>(&self, action: Action, pid: i32, signal: Option<Signal>, trigger: Trigger, result: &nix::Result<T>) {
+        let mut audit = self.audit.lock();
+        let Some(log) = audit.as_mut() else {
+            return;
+        };
+        let (name, uid) = describe_process(&self.snapshot, &self.sampler.proc_root(), pid);
+        let entry = Entry {
+            time: Local::now(),
+            action,
+            signal: signal.map(|signal| signal.as_str().to_string()),
+            pid,
+            name: name.unwrap_or_default(),
+            user: uid.map(|uid| log.user_name(uid)),
+            mode: self.mode,
+            trigger,
+            outcome: result.into(),
+        };
+        log.record(&entry);
+    }
+
+    fn pause(&mut self, pid: i32, reason: PauseReason, trigger: Trigger) -> nix::Result<()> {
+        self.signal(pid, Signal::SIGSTOP, trigger)?;
+        self.paused.insert(pid, reason);
+        Ok(())
+    }
+
+    /// Carries out `Limiter::pause_now` and `resume_now`. Resumed processes
+    /// stay out of pauses and the duty cycle for a while.
+    fn handle_requests(&mut self, protected: &[String]) {
+        self.spared.retain(|_, since| since.elapsed() < SPARE_DURATION);
+        let expired: Vec<i32> =
+            self.manual_paused.iter().filter(|(_, since)| since.elapsed() >= MANUAL_PAUSE_LIMIT).map(|(pid, _)| *pid).collect();
+        for pid in expired {
+            self.manual_paused.remove(&pid);
+            if self.paused.remove(pid) {
+                log::info!("Resuming pid {}, paused by hand {} minutes ago", pid, MANUAL_PAUSE_LIMIT.as_secs() / 60);
+                let _ = self.signal(pid, Signal::SIGCONT, Trigger::Released);
+            }
+        }
+
+        let requests = std::mem::take(&mut *self.requests.lock());
+        for request in requests {
+            match request {
+                ProcessRequest::Pause(pid) => {
+                    if self.paused.contains(pid) {
+                        continue;
+                    }
+                    let (name, uid) = describe_process(&self.snapshot, &self.sampler.proc_root(), pid);
+                    let name = name.unwrap_or_default();
+                    if let Some(reason) = manual_pause_refusal(pid, &name, uid, protected) {
+                        log::warn!("Not pausing {} ({}): {}", name, pid, reason);
+                        continue;
+                    }
+                    self.spared.remove(&pid);
+                    self.duty_pids.remove(&pid);
+                    if self.pause(pid, PauseReason::Manual, Trigger::Manual).is_ok() {
+                        self.manual_paused.insert(pid, Instant::now());
+                    }
+                }
+                ProcessRequest::Resume(pid) => {
+                    self.manual_paused.remove(&pid);
+                    self.spared.insert(pid, Instant::now());
+                    self.duty_pids.remove(&pid);
+                    if self.paused.remove(pid) {
+                        self.memory_holds.remove(&pid);
+                    }
+                    let _ = self.signal(pid, Signal::SIGCONT, Trigger::Manual);
+                }
+            }
+        }
+    }
+
+    /// Forgets exited processes right away, and while targeting, adds new
+    /// processes that exec under a target name or fork off a target, and drops
+    /// targets that exec into something else, unless they are in the tree.
+    #[cfg(target_os = "linux")]
+    fn handle_process_events(
+        &mut self,
+        targeting: bool,
+        names: &[String],
+        cgroups: &[String],
+        protected: &[String],
+        include_children: bool,
+    ) {
+        let events = std::mem::take(&mut *self.events.lock());
+        let myself = std::process::id() as i32;
+        let proc_root = self.sampler.proc_root();
+        for event in events {
+            let pid = match event {
+                Event::Exit { pid } => {
+                    self.forget(pid);
+                    continue;
+                }
+                Event::Exec { pid } if targeting && (!names.is_empty() || !cgroups.is_empty()) => {
+                    let name = process_name(&proc_root, pid).unwrap_or_default();
+                    let in_cgroup = !cgroups.is_empty()
+                        && !is_protected(protected, &name)
+                        && std::fs::read_to_string(proc_root.join(pid.to_string()).join("cgroup"))
+                            .ok()
+                            .and_then(|contents| cgroup::parse(&contents))
+                            .is_some_and(|path| cgroups.iter().any(|target| cgroup::contains(target, &path)));
+                    if !in_cgroup && !names.iter().any(|n| n.eq_ignore_ascii_case(&name)) {
+                        let tree_root = self.targeted_pid.filter(|_| include_children);
+                        if self.named_targets.contains(&pid)
+                            && !tree_root.is_some_and(|root| self.descends_from(&proc_root, pid, root))
+                        {
+                            log::debug!("process events: {} became {}, no longer a target", pid, name);
+                            self.named_targets.retain(|p| *p != pid);
                         }
+                        continue;
+                    }
+                    pid
+                }
+                // Children share the name of a named target, or are in the tree
+                Event::Fork { parent, child }
+                    if targeting
+                        && (self.named_targets.contains(&parent)
+                            || (include_children && self.targeted_pid == Some(parent))) =>
+                {
+                    child
+                }
+                _ => continue,
+            };
+            if pid != myself && !self.named_targets.contains(&pid) {
+                log::debug!("process events: targeting new process {}", pid);
+                self.named_targets.push(pid);
+            }
+        }
+    }
+
+    /// Whether `root` is `pid` or one of its ancestors.
+    #[cfg(target_os = "linux")]
+    fn descends_from(&self, proc_root: &Path, pid: i32, root: i32) -> bool {
+        let mut current = Some(pid);
+        while let Some(pid) = current {
+            if pid == root {
+                return true;
+            }
+            current = match self.snapshot.process(pid) {
+                Some(process) => process.parent,
+                None => crate::procfs::read_parent(proc_root, pid),
+            };
+        }
+        false
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn handle_process_events(
+        &mut self,
+        _targeting: bool,
+        _names: &[String],
+        _cgroups: &[String],
+        _protected: &[String],
+        _include_children: bool,
+    ) {
+    }
+
+    /// Drops an exited process from everything kept about it.
+    #[cfg(target_os = "linux")]
+    fn forget(&mut self, pid: i32) {
+        self.named_targets.retain(|p| *p != pid);
+        self.thermal_throttled.retain(|p| *p != pid);
+        self.duty_pids.remove(&pid);
+        self.paused.remove(pid);
+        self.escalation.retain(|(p, _), _| *p != pid);
+        self.memory_holds.remove(&pid);
+        self.memory_skipped.retain(|(p, _), _| *p != pid);
+        self.spared.remove(&pid);
+        self.manual_paused.remove(&pid);
+    }
+
+    fn resume_all(&mut self, reason: PauseReason) {
+        while let Some(pid) = self.paused.take_oldest(reason) {
+            let _ = self.signal(pid, Signal::SIGCONT, Trigger::Released);
+        }
+    }
+
+    fn check_escalation(&mut self, policy: &EscalationPolicy, protected: &[String]) {
+        let now = Instant::now();
+        let myself = std::process::id() as i32;
+        let throttle_share = policy.throttle_percent.clamp(1, 100) as f32 / 100.0;
+
+        // Forget processes that are gone
+        let snapshot = self.snapshot.clone();
+        self.escalation
+            .retain(|(pid, start_time), _| snapshot.process(*pid).is_some_and(|p| p.start_time == *start_time));
+
+        let mut steps = Vec::new();
+        for (&pid_i32, process) in &snapshot.processes {
+            if pid_i32 <= 1
+                || pid_i32 == myself
+                || Some(pid_i32) == self.targeted_pid
+                || self.paused.contains(pid_i32)
+            {
+                continue;
+            }
+
+            let usage = process.cpu_usage;
+            let key = (pid_i32, process.start_time);
+            let stage = self.escalation.get(&key).copied();
+            // A throttled process only gets its share, so judge what it would use unthrottled.
+            let demand = match stage {
+                Some(s) if s.is_throttled() => usage / throttle_share,
+                _ => usage,
+            };
+            let over = demand > policy.threshold_percent;
+            let stage = match stage {
+                Some(s) => s,
+                None if over => EscalationStage::Watching { over_since: now },
+                None => continue,
+            };
+
+            let name = process.name.clone();
+            let (next, step) = stage.advance(now, over, is_protected(protected, &name), policy);
+            match next {
+                Some(next) => self.escalation.insert(key, next),
+                None => self.escalation.remove(&key),
+            };
+            if let Some(step) = step {
+                steps.push((pid_i32, name, step, usage));
+            }
+        }
+
+        for (pid, name, step, usage) in steps {
+            let trigger = Trigger::Escalation { cpu_percent: usage, step };
+            let result = match step {
+                EscalationStep::Throttled | EscalationStep::Protected => Ok(()),
+                EscalationStep::Released => self.signal(pid, Signal::SIGCONT, trigger),
+                EscalationStep::Signaled(signal) => {
+                    // Let a stopped process see the signal
+                    let _ = send_signal(pid, Signal::SIGCONT);
+                    self.signal(pid, signal, trigger)
+                }
+                EscalationStep::Killed => self.signal(pid, Signal::SIGKILL, trigger),
+            };
+            log::info!("escalation: {:?} {} ({}) at {:.1}% CPU", step, name, pid, usage);
 
-                        sys.refresh_cpu_all();
-                        let total_load = sys.global_cpu_usage();
-                        let limit_f32 = limit as f32;
-                        let lower_threshold = (limit_f32 - GLOBAL_HYSTERESIS).max(0.0);
-
-                        if total_load > limit_f32 {
-                            sys.refresh_processes(sysinfo::ProcessesToUpdate::All, true);
-                            let myself = std::process::id() as i32;
-
-                            let mut candidates: Vec<_> = sys
-                                .processes()
-                                .iter()
-                                .filter_map(|(pid, process)| {
-                                    let pid_i32 = pid.as_u32() as i32;
-                                    if pid_i32 == myself || paused_global_set.contains(&pid_i32) {
-                                        return None;
-                                    }
-                                    let usage = process.cpu_usage();
-                                    if usage <= 0.5 {
-                                        return None;
-                                    }
-                                    Some((pid_i32, usage))
-                                })
-                                .collect();
-
-                            candidates.sort_by(|a, b| {
-                                b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal)
-                            });
-
-                            if let Some((pid, _)) = candidates.first() {
-                                let pid_i32 = *pid;
-                                if kill(Pid::from_raw(pid_i32), Signal::SIGSTOP).is_ok() {
-                                    paused_global_set.insert(pid_i32);
-                                    paused_global.push_back(pid_i32);
-                                    // Update status
-                                    {
-                                        let mut status = status_handle.lock();
-                                        status.pause_count += 1;
-                                        status.last_action_time = Some(std::time::SystemTime::now());
-                                    }
+            let mut status = self.status.lock();
+            if status.escalations.len() >= MAX_ESCALATION_RECORDS {
+                status.escalations.remove(0);
+            }
+            status.escalations.push(EscalationRecord {
+                pid,
+                name,
+                step,
+                cpu_usage: usage,
+                time: SystemTime::now(),
+                error: result.err().map(|e| e.to_string()),
+            });
+        }
+    }
+
+    fn check_memory(&mut self, protected: &[String]) {
+        let snapshot = self.snapshot.clone();
+        let available = snapshot.memory_available;
+        let myself = std::process::id() as i32;
+        let euid = nix::unistd::geteuid().as_raw();
+        let now = Instant::now();
+
+        // Forget processes that are gone
+        self.memory_holds.retain(|pid, _| snapshot.processes.contains_key(pid));
+        self.memory_skipped.retain(|(pid, start_time), until| {
+            until.is_none_or(|until| until > now) && snapshot.process(*pid).is_some_and(|p| p.start_time == *start_time)
+        });
+
+        let can_act = |pid: i32, process: &Process| {
+            memory_eligible(process, protected, euid, myself)
+                && !self.memory_skipped.contains_key(&(pid, process.start_time))
+                && !self.paused.contains(pid)
+                && !self.spared.contains_key(&pid)
+        };
+
+        let mut pressure = false;
+        let mut actions: Vec<(i32, usize, MemoryAction)> = Vec::new();
+        let mut releases = Vec::new();
+        let mut rests = Vec::new(); // Released without recovering, left alone for a while
+        for (index, rule) in self.memory_rules.iter().enumerate() {
+            let pending = |pid: i32, actions: &[(i32, usize, MemoryAction)]| {
+                self.memory_holds.contains_key(&pid) || actions.iter().any(|(p, _, _)| *p == pid)
+            };
+            match rule.trigger {
+                MemoryTrigger::ProcessRss { bytes } => {
+                    for (&pid_i32, process) in &snapshot.processes {
+                        let rss = process.memory;
+                        match self.memory_holds.get(&pid_i32) {
+                            Some(hold) if hold.rule == index => {
+                                // Stopped, it cannot shrink: let it go once memory was freed
+                                // elsewhere, or after a while
+                                let stuck = hold.action == MemoryAction::Pause
+                                    && (now - hold.since >= MEMORY_PAUSE_LIMIT
+                                        || available >= hold.available.saturating_add(rss));
+                                if rss < bytes - bytes / 10 {
+                                    releases.push(pid_i32);
+                                } else if stuck {
+                                    rests.push(pid_i32);
                                 } else {
-                                    paused_global_set.remove(&pid_i32);
+                                    pressure = true;
                                 }
                             }
-                            // Update current paused list
-                            {
-                                let mut status = status_handle.lock();
-                                status.currently_paused_pids = paused_global.iter().copied().collect();
-                                status.is_actively_limiting = !paused_global.is_empty();
-                            }
-                        } else if total_load < lower_threshold {
-                            if let Some(pid) = paused_global.pop_front() {
-                                let _ = kill(Pid::from_raw(pid), Signal::SIGCONT);
-                                paused_global_set.remove(&pid);
-                            }
-                            // Update current paused list
+                            None if rss > bytes
+                                && !pending(pid_i32, &actions)
+                                && can_act(pid_i32, process) =>
                             {
-                                let mut status = status_handle.lock();
-                                status.currently_paused_pids = paused_global.iter().copied().collect();
-                                status.is_actively_limiting = !paused_global.is_empty();
+                                pressure = true;
+                                actions.push((pid_i32, index, rule.action));
                             }
+                            _ => {}
+                        }
+                    }
+                }
+                MemoryTrigger::AvailableBelow { bytes } => {
+                    let holds = self.memory_holds.iter().filter(|(_, hold)| hold.rule == index);
+                    let oldest_hold = holds.clone().min_by_key(|(_, hold)| hold.since).map(|(pid, _)| *pid);
+                    // Freeing memory takes a while: give the last process time to exit
+                    let waiting = holds.map(|(_, hold)| hold.since).max().is_some_and(|since| now - since < MEMORY_COOLDOWN);
+                    if available < bytes {
+                        pressure = true;
+                        let largest = snapshot
+                            .processes
+                            .values()
+                            .filter(|process| {
+                                !pending(process.pid, &actions) && can_act(process.pid, process)
+                            })
+                            .max_by_key(|process| process.memory);
+                        if let Some(process) = largest.filter(|_| !waiting) {
+                            actions.push((process.pid, index, rule.action));
                         }
+                    } else if available >= bytes + bytes / 10 {
+                        // Recovered: release one process per check
+                        releases.extend(oldest_hold);
+                    } else if oldest_hold.is_some() {
+                        pressure = true;
+                    }
+                }
+            }
+        }
 
-                        thread::sleep(Duration::from_millis(PERIOD_MS));
+        for (pid, rule, action) in actions {
+            let (name, start_time) = snapshot.process(pid).map(|p| (p.name.clone(), p.start_time)).unwrap_or_default();
+            let trigger = Trigger::Memory { rule: self.memory_rules[rule].trigger };
+            let result = match action {
+                MemoryAction::Pause => self.pause(pid, PauseReason::Memory, trigger).map(|_| None),
+                MemoryAction::Deprioritize => {
+                    let result = renice(pid, DEPRIORITIZED_NICE);
+                    self.audit(Action::Renice, pid, None, trigger, &result);
+                    result.map(Some)
+                }
+                MemoryAction::Terminate => {
+                    let _ = send_signal(pid, Signal::SIGCONT);
+                    self.signal(pid, Signal::SIGTERM, trigger).map(|_| None)
+                }
+            };
+            match result {
+                Ok(previous_nice) => {
+                    log::info!("memory: {:?} {} ({})", action, name, pid);
+                    self.memory_holds.insert(pid, MemoryHold { rule, action, since: now, previous_nice, available });
+                    let mut status = self.status.lock();
+                    if action == MemoryAction::Pause {
+                        status.pause_count += 1;
+                    }
+                    status.last_action_time = Some(SystemTime::now());
+                }
+                Err(e) => {
+                    // Not ours to signal; trying again would only fail again
+                    if e == Errno::EPERM {
+                        self.memory_skipped.insert((pid, start_time), None);
                     }
+                    log::warn!("memory: {:?} {} ({}) failed: {}", action, name, pid, e);
                 }
             }
-        });
+        }
+
+        for pid in releases {
+            self.release_memory_hold(pid, true);
+        }
+        for pid in rests {
+            if let Some(process) = snapshot.process(pid) {
+                self.memory_skipped.insert((pid, process.start_time), Some(now + MEMORY_COOLDOWN));
+            }
+            self.release_memory_hold(pid, false);
+        }
+
+        self.status.lock().memory_pressure = pressure;
+    }
+
+    /// `recovered` when memory is back, rather than the rules having changed.
+    fn release_memory_hold(&mut self, pid: i32, recovered: bool) {
+        let Some(hold) = self.memory_holds.remove(&pid) else {
+            return;
+        };
+        let trigger = match self.memory_rules.get(hold.rule) {
+            Some(rule) if recovered => Trigger::Memory { rule: rule.trigger },
+            _ => Trigger::Released,
+        };
+        match hold.action {
+            MemoryAction::Pause => {
+                if self.paused.remove(pid) {
+                    let _ = self.signal(pid, Signal::SIGCONT, trigger);
+                }
+            }
+            MemoryAction::Deprioritize => {
+                // Lowering nice again may need CAP_SYS_NICE
+                if let Some(nice) = hold.previous_nice {
+                    let result = renice(pid, nice);
+                    self.audit(Action::Renice, pid, None, trigger, &result);
+                    if let Err(e) = result {
+                        log::warn!("memory: could not restore nice {} for {}: {}", nice, pid, e);
+                    }
+                }
+            }
+            MemoryAction::Terminate => {}
+        }
+        log::info!("memory: released {}", pid);
+    }
+
+    fn release_memory(&mut self) {
+        let pids: Vec<i32> = self.memory_holds.keys().copied().collect();
+        for pid in pids {
+            self.release_memory_hold(pid, false);
+        }
+        self.status.lock().memory_pressure = false;
+    }
+
+    fn release_escalation(&mut self) {
+        let throttled: Vec<i32> = self
+            .escalation
+            .drain()
+            .filter(|(_, stage)| stage.is_throttled())
+            .map(|((pid, _), _)| pid)
+            .collect();
+        for pid in throttled {
+            let _ = self.signal(pid, Signal::SIGCONT, Trigger::Released);
+        }
+    }
+
+    fn release_all(&mut self) {
+        if let Some(pid) = self.targeted_pid.take() {
+            self.duty_pids.insert(pid);
+        }
+        for pid in std::mem::take(&mut self.duty_pids) {
+            let _ = self.signal(pid, Signal::SIGCONT, Trigger::Released);
+        }
+        self.named_targets.clear();
+        self.thermal_throttled.clear();
+        self.resume_all(PauseReason::Global);
+        self.release_escalation();
+        self.release_memory();
     }
 }
 
@@ -289,9 +1634,13 @@ mod tests {
         assert_eq!(state.is_active, false);
 
         // Set target
-        limiter.set_target(1234);
+        limiter.set_target(1234).unwrap();
         let state = limiter.get_state();
         assert_eq!(state.target_pid, Some(1234));
+        for pid in [-1, 0, 1, std::process::id() as i32] {
+            assert!(limiter.set_target(pid).is_err(), "{}", pid);
+        }
+        assert_eq!(limiter.get_state().target_pid, Some(1234));
         assert_eq!(state.mode, LimiterMode::Targeted);
 
         // Toggle active
@@ -306,9 +1655,89 @@ mod tests {
         assert_eq!(state.limit_percentage, 80);
 
         // Back to target
-        limiter.set_target(5678);
+        limiter.set_target(5678).unwrap();
         let state = limiter.get_state();
         assert_eq!(state.mode, LimiterMode::Targeted);
         assert_eq!(state.target_pid, Some(5678));
+
+        // Resuming the target releases it; anything else waits for the worker
+        limiter.resume_now(5678);
+        assert_eq!(limiter.get_state().target_pid, None);
+        limiter.pause_now(4321);
+        limiter.resume_now(4321);
+        assert_eq!(*limiter.requests.lock(), vec![ProcessRequest::Pause(4321), ProcessRequest::Resume(4321)]);
+    }
+
+    #[test]
+    fn test_memory_rules_spare_protected() {
+        let protected = vec!["Xorg".to_string()];
+        let process = |pid: i32, name: &str, uid: u32| Process { pid, name: name.to_string(), uid: Some(uid), ..Default::default() };
+        // Pausing or renicing the display server is as bad as killing it
+        assert!(!memory_eligible(&process(4321, "Xorg", 1000), &protected, 1000, 99));
+        assert!(memory_eligible(&process(4321, "firefox", 1000), &protected, 1000, 99));
+        assert!(!memory_eligible(&process(1, "systemd", 1000), &protected, 1000, 99));
+        assert!(!memory_eligible(&process(99, "cpu_limiter", 1000), &protected, 1000, 99));
+        assert!(!memory_eligible(&process(4321, "firefox", 1001), &protected, 1000, 99));
+        assert!(memory_eligible(&process(4321, "firefox", 1001), &protected, 0, 99));
+    }
+
+    #[test]
+    fn test_manual_pause_refusal() {
+        let protected = vec!["Xorg".to_string()];
+        let euid = nix::unistd::geteuid().as_raw();
+        assert!(manual_pause_refusal(1, "init", Some(0), &protected).is_some());
+        assert!(manual_pause_refusal(std::process::id() as i32, "cpu_limiter", Some(euid), &protected).is_some());
+        assert!(manual_pause_refusal(4321, "xorg", Some(euid), &protected).is_some());
+        assert!(manual_pause_refusal(4321, "make", Some(euid), &protected).is_none());
+        assert!(manual_pause_refusal(4321, "make", None, &protected).is_none());
+        // Root may pause anyone's processes
+        assert_eq!(manual_pause_refusal(4321, "make", Some(euid + 1), &protected).is_some(), euid != 0);
+    }
+
+    #[test]
+    fn test_escalation_ladder() {
+        let policy = EscalationPolicy {
+            sustain_secs: 60,
+            escalate_after_secs: 120,
+            grace_secs: 5,
+            ..Default::default()
+        };
+        let t0 = Instant::now();
+        let at = |secs| t0 + Duration::from_secs(secs);
+
+        let stage = EscalationStage::Watching { over_since: t0 };
+        assert_eq!(stage.advance(at(30), true, false, &policy), (Some(stage), None));
+        assert_eq!(stage.advance(at(30), false, false, &policy), (None, None));
+
+        let (stage, step) = stage.advance(at(60), true, false, &policy);
+        assert_eq!(step, Some(EscalationStep::Throttled));
+        let stage = stage.unwrap();
+        assert!(stage.is_throttled());
+        assert_eq!(stage.advance(at(90), false, false, &policy), (None, Some(EscalationStep::Released)));
+
+        let (stage, step) = stage.advance(at(180), true, false, &policy);
+        assert_eq!(step, Some(EscalationStep::Signaled(Signal::SIGTERM)));
+        let (_, step) = stage.unwrap().advance(at(185), false, false, &policy);
+        assert_eq!(step, Some(EscalationStep::Killed));
+
+        for text in ["SIGUSR1", "usr1", " 10 "] {
+            assert_eq!(parse_signal(text), Some(Signal::SIGUSR1));
+        }
+        assert_eq!(parse_signal("SIGNOPE"), None);
+        assert_eq!(parse_signal("0"), None);
+    }
+
+    #[test]
+    fn test_escalation_protected() {
+        let policy = EscalationPolicy::default();
+        assert!(is_protected(&["Xorg".to_string()], "xorg"));
+
+        let t0 = Instant::now();
+        let stage = EscalationStage::Throttled { since: t0 };
+        let later = t0 + Duration::from_secs(policy.escalate_after_secs);
+        let (stage, step) = stage.advance(later, true, true, &policy);
+        assert_eq!(stage, Some(EscalationStage::Protected));
+        assert_eq!(step, Some(EscalationStep::Protected));
+        assert!(stage.unwrap().is_throttled());
     }
 }
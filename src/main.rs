@@ -1,109 +1,220 @@
 use eframe::egui;
 use limiter::Limiter;
 use std::sync::Arc;
-use std::fs::{File, OpenOptions};
-use std::io::Write;
 use tray_icon::{
-    Icon, TrayIconBuilder,
-    menu::{Menu, MenuId, MenuItem},
+    TrayIconBuilder,
+    menu::{CheckMenuItem, Menu, MenuId, MenuItem, PredefinedMenuItem, Submenu},
 };
-use ui::CpuLimiterApp;
-use std::path::PathBuf;
+use cli::{AutostartAction, Command, DaemonOptions, ExportOptions};
+use control::Request;
+use instance::InstanceLock;
+use ui::{CpuLimiterApp, LIMIT_PRESETS, TrayMenu};
 
+mod audit;
+#[cfg(target_os = "linux")]
+mod autostart;
+mod cgroup;
+mod cli;
+mod config;
+#[cfg(target_os = "linux")]
+mod connector;
+mod control;
+mod daemon;
+mod history;
+mod http;
+mod indicator;
+mod instance;
 mod limiter;
+mod metrics;
+mod notify;
+mod power;
+#[cfg(target_os = "linux")]
+mod procfs;
+mod sampler;
+mod schedule;
+#[cfg(test)]
+mod testing;
+mod thermal;
 mod ui;
+mod wrapper;
 
-// Estrutura para gerenciar o lock de instância única
-struct SingleInstanceLock {
-    _file: File,
-    lock_path: PathBuf,
+#[cfg(target_os = "macos")]
+fn set_tray_fixed_length(tray_icon: &tray_icon::TrayIcon) {
+    if let Some(status_item) = tray_icon.ns_status_item() {
+        // Fixed width prevents icon shifting when title changes.
+        status_item.setLength(60.0);
+    }
 }
 
-impl SingleInstanceLock {
-    fn try_acquire() -> Result<Self, Box<dyn std::error::Error>> {
-        let lock_path = std::env::temp_dir().join("cpu-limiter.lock");
-        
-        // Tenta criar o arquivo de lock exclusivamente
-        let file = OpenOptions::new()
-            .write(true)
-            .create_new(true)
-            .open(&lock_path);
-        
-        match file {
-            Ok(mut f) => {
-                // Escreve o PID no arquivo de lock
-                writeln!(f, "{}", std::process::id())?;
-                Ok(Self {
-                    _file: f,
-                    lock_path,
-                })
-            }
-            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
-                // Arquivo já existe, verifica se o processo ainda está ativo
-                if let Ok(content) = std::fs::read_to_string(&lock_path) {
-                    if let Ok(pid) = content.trim().parse::<i32>() {
-                        // Verifica se o processo ainda existe
-                        use std::process::Command;
-                        let output = Command::new("ps")
-                            .arg("-p")
-                            .arg(pid.to_string())
-                            .output();
-                        
-                        if let Ok(output) = output {
-                            if output.status.success() {
-                                // Processo ainda existe
-                                return Err("Outra instância do CPU Limiter já está rodando!".into());
-                            }
-                        }
-                    }
-                }
-                
-                // Processo não existe mais, remove o lock antigo e tenta novamente
-                let _ = std::fs::remove_file(&lock_path);
-                let mut f = OpenOptions::new()
-                    .write(true)
-                    .create_new(true)
-                    .open(&lock_path)?;
-                writeln!(f, "{}", std::process::id())?;
-                Ok(Self {
-                    _file: f,
-                    lock_path,
-                })
-            }
-            Err(e) => Err(e.into()),
+fn acquire_instance_lock(force: bool) -> InstanceLock {
+    match instance::lock_path().map_err(anyhow::Error::from).and_then(|path| InstanceLock::acquire(&path, force)) {
+        Ok(lock) => lock,
+        Err(e) => {
+            eprintln!("{:#}", e);
+            std::process::exit(1);
         }
     }
 }
 
-impl Drop for SingleInstanceLock {
-    fn drop(&mut self) {
-        // Remove o arquivo de lock quando a aplicação terminar
-        let _ = std::fs::remove_file(&self.lock_path);
+/// Drives the running instance, or does the work here when there is none.
+fn run_control(request: Request, json: bool) -> anyhow::Result<()> {
+    if request == Request::Subscribe {
+        let running = control::subscribe(|event| {
+            if let Ok(line) = serde_json::to_string(event) {
+                println!("{}", line);
+            }
+        })?;
+        anyhow::ensure!(running, "CPU Limiter is not running");
+        return Ok(());
+    }
+    if let Some(response) = control::send(&request)? {
+        return cli::print_response(&response, json);
     }
+
+    let options = match request {
+        Request::Limit { pid, percent } => DaemonOptions {
+            pid: Some(pid),
+            limit: Some(percent),
+            ..Default::default()
+        },
+        Request::Global { percent } => DaemonOptions {
+            global: Some(percent),
+            ..Default::default()
+        },
+        Request::Release { pid: Some(pid) } => {
+            // A limiter that died mid-cycle may have left it stopped
+            nix::sys::signal::kill(nix::unistd::Pid::from_raw(pid), nix::sys::signal::Signal::SIGCONT)?;
+            return Ok(());
+        }
+        Request::Release { pid: None } | Request::Stop => anyhow::bail!("CPU Limiter is not running"),
+        Request::Status if json => {
+            println!("{}", serde_json::json!({ "is_running": false }));
+            std::process::exit(3);
+        }
+        Request::Status => {
+            println!("CPU Limiter is not running");
+            std::process::exit(3);
+        }
+        // Only sent by other clients of the socket protocol
+        _ => anyhow::bail!("CPU Limiter is not running"),
+    };
+
+    // Standalone: limit in the foreground until Ctrl+C
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let _instance_lock = acquire_instance_lock(false);
+    daemon::run(options)
 }
 
-#[cfg(target_os = "macos")]
-fn set_tray_fixed_length(tray_icon: &tray_icon::TrayIcon) {
-    if let Some(status_item) = tray_icon.ns_status_item() {
-        // Fixed width prevents icon shifting when title changes.
-        status_item.setLength(60.0);
+/// Writes the running instance's samples and the audit log to a file or stdout.
+fn run_export(options: ExportOptions) -> anyhow::Result<()> {
+    let samples = match control::send(&Request::History)? {
+        Some(control::Response::History { samples }) => samples,
+        Some(control::Response::Error { message }) => anyhow::bail!("{}", message),
+        Some(response) => anyhow::bail!("unexpected response {:?}", response),
+        None => {
+            eprintln!("CPU Limiter is not running, exporting the audit log only");
+            Vec::new()
+        }
+    };
+    let audit = config::Config::load(&config::default_path()).map(|c| c.audit).unwrap_or_default();
+    let actions = audit::read(&audit::default_path(), audit.files);
+    let text = history::export(&samples, &actions, options.format)?;
+    match options.output {
+        Some(path) => std::fs::write(&path, text)?,
+        None => print!("{}", text),
     }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn run_autostart(action: AutostartAction) -> anyhow::Result<()> {
+    use autostart::{Autostart, Method};
+    let autostart = Autostart::from_env();
+    let exe = std::env::current_exe()?;
+    match action {
+        AutostartAction::Status => {}
+        AutostartAction::Gui => autostart.install(Method::Desktop, &exe)?,
+        AutostartAction::Daemon => autostart.install(Method::Systemd, &exe)?,
+        AutostartAction::Off => autostart.remove()?,
+    }
+    match autostart.installed() {
+        Some(Method::Desktop) => println!("starts at login: window, minimized to the tray"),
+        Some(Method::Systemd) => println!("starts at login: daemon, as the cpu-limiter.service systemd user unit"),
+        None => println!("does not start at login"),
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn run_autostart(_action: AutostartAction) -> anyhow::Result<()> {
+    anyhow::bail!("autostart is only managed from the command line on Linux; use Start at Login in the window")
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let command = match cli::parse(std::env::args().skip(1)) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+
+    let (start_minimized, force) = match command {
+        Command::Gui { minimized, force } => (minimized, force),
+        Command::Daemon(options) => {
+            // Headless runs have no window to show status, so log by default
+            env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+            let _instance_lock = acquire_instance_lock(options.force);
+            return daemon::run(options).map_err(|e| e.into());
+        }
+        Command::Control { request, json } => {
+            if let Err(e) = run_control(request, json) {
+                eprintln!("{:#}", e);
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Command::Autostart(action) => {
+            if let Err(e) = run_autostart(action) {
+                eprintln!("{:#}", e);
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Command::Export(options) => {
+            if let Err(e) = run_export(options) {
+                eprintln!("{:#}", e);
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Command::Run(options) => {
+            env_logger::init();
+            match wrapper::run(options) {
+                Ok(code) => std::process::exit(code),
+                Err(e) => {
+                    eprintln!("{:#}", e);
+                    std::process::exit(127);
+                }
+            }
+        }
+    };
+
     env_logger::init();
 
     #[cfg(target_os = "macos")]
     ensure_launch_agent();
 
-    // Verificar se deve iniciar minimizado
-    let start_minimized = std::env::args().any(|arg| arg == "--minimized" || arg == "--background");
-
-    // Garantir que apenas uma instância está rodando
-    let _instance_lock = match SingleInstanceLock::try_acquire() {
+    // Garantir que apenas uma instância está rodando; uma segunda execução
+    // repassa seus argumentos (ex.: mostrar a janela) para a primeira
+    let _instance_lock = match instance::lock_path().map_err(anyhow::Error::from).and_then(|path| InstanceLock::acquire(&path, force)) {
         Ok(lock) => lock,
         Err(e) => {
-            eprintln!("{}", e);
+            let args = std::env::args().skip(1).collect();
+            if !force && let Ok(Some(control::Response::Ok)) = control::send(&Request::Activate { args }) {
+                return Ok(());
+            }
+            eprintln!("{:#}", e);
             std::process::exit(1);
         }
     };
@@ -120,24 +231,45 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         native_options,
         Box::new(move |_cc| {
             let limiter = Arc::new(Limiter::new());
+            limiter.configure_from_env();
             limiter.start_background_task();
 
-            // Load tray icon from embedded file (template icon for macOS)
-            let icon_bytes = include_bytes!("tray.png");
-            let image = image::load_from_memory(icon_bytes)
-                .expect("Failed to load icon")
-                .into_rgba8();
-            let (width, height) = image.dimensions();
-            let rgba = image.into_raw();
-            let icon = Icon::from_rgba(rgba, width, height)
-                .expect("Failed to create icon from RGBA");
-
-            // Menu
+            // Static icon until the app draws the live one, if configured
+            let icon = indicator::static_icon();
+
+            // Menu; texts and check marks are filled in from the limiter by the app
             let tray_menu = Menu::new();
+            let toggle_i = MenuItem::with_id("tray-toggle", "Activate limiter", true, None);
+            let mode_menu = Submenu::new("Mode", true);
+            let targeted_i = CheckMenuItem::with_id("tray-mode-targeted", "Targeted", true, false, None);
+            let global_i = CheckMenuItem::with_id("tray-mode-global", "Global", true, false, None);
+            mode_menu.append_items(&[&targeted_i, &global_i])
+                .expect("Failed to append tray menu items");
+            let limit_menu = Submenu::new("Limit", true);
+            let preset_items: Vec<CheckMenuItem> = LIMIT_PRESETS
+                .iter()
+                .map(|percent| CheckMenuItem::with_id(TrayMenu::limit_id(*percent), format!("{}%", percent), true, false, None))
+                .collect();
+            for item in &preset_items {
+                limit_menu.append(item).expect("Failed to append tray menu items");
+            }
+            let paused_menu = Submenu::new("Paused processes", false);
+            let top_menu = Submenu::new("Top processes", false);
+            let profiles_menu = Submenu::new("Profiles", false);
             let quit_menu_id = MenuId::new("tray-quit");
             let quit_i = MenuItem::with_id(quit_menu_id.clone(), "Sair", true, None);
-            tray_menu.append(&quit_i)
-                .expect("Failed to append quit menu item");
+            tray_menu.append_items(&[
+                &toggle_i,
+                &mode_menu,
+                &limit_menu,
+                &paused_menu,
+                &top_menu,
+                &PredefinedMenuItem::separator(),
+                &profiles_menu,
+                &PredefinedMenuItem::separator(),
+                &quit_i,
+            ])
+                .expect("Failed to append tray menu items");
 
             let tray_icon = TrayIconBuilder::new()
                 .with_menu(Box::new(tray_menu))
@@ -160,7 +292,16 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 _cc,
                 limiter.clone(),
                 tray_icon,
-                quit_menu_id,
+                TrayMenu {
+                    quit_id: quit_menu_id,
+                    toggle: toggle_i,
+                    targeted: targeted_i,
+                    global: global_i,
+                    presets: preset_items,
+                    paused: paused_menu,
+                    top: top_menu,
+                    profiles: profiles_menu,
+                },
             )))
         }),
     )
@@ -180,7 +321,7 @@ fn ensure_launch_agent() {
     }
 
     let home = match std::env::var_os("HOME") {
-        Some(h) => PathBuf::from(h),
+        Some(h) => std::path::PathBuf::from(h),
         None => return,
     };
     let agent_dir = home.join("Library/LaunchAgents");
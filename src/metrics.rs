@@ -0,0 +1,93 @@
+use crate::limiter::{self, Limiter, LimiterMode};
+use crate::sampler::Snapshot;
+use std::fmt::Write;
+
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Prometheus text exposition of the host, as of `snapshot`, and the limiter.
+pub fn render(limiter: &Limiter, snapshot: &Snapshot) -> String {
+    let state = limiter.get_state();
+    let status = limiter.get_status();
+
+    let mut out = String::new();
+    metric(&mut out, "cpu_limiter_cpu_usage_percent", "gauge", "Total CPU usage across all cores.");
+    let _ = writeln!(out, "cpu_limiter_cpu_usage_percent {}", snapshot.cpu_percent);
+    metric(&mut out, "cpu_limiter_core_usage_percent", "gauge", "CPU usage per core.");
+    for (index, usage) in snapshot.per_core.iter().enumerate() {
+        let _ = writeln!(out, "cpu_limiter_core_usage_percent{{core=\"{}\"}} {}", index, usage);
+    }
+
+    metric(&mut out, "cpu_limiter_memory_used_bytes", "gauge", "Memory in use.");
+    let _ = writeln!(out, "cpu_limiter_memory_used_bytes {}", snapshot.memory_used);
+    metric(&mut out, "cpu_limiter_memory_total_bytes", "gauge", "Installed memory.");
+    let _ = writeln!(out, "cpu_limiter_memory_total_bytes {}", snapshot.memory_total);
+
+    metric(&mut out, "cpu_limiter_active", "gauge", "1 while the limiter is switched on.");
+    let _ = writeln!(out, "cpu_limiter_active {}", state.is_active as u8);
+    metric(&mut out, "cpu_limiter_limit_percent", "gauge", "Configured limit, by mode.");
+    let mode = match state.mode {
+        LimiterMode::Targeted => "targeted",
+        LimiterMode::Global => "global",
+    };
+    let _ = writeln!(out, "cpu_limiter_limit_percent{{mode=\"{}\"}} {}", mode, state.limit_percentage);
+
+    metric(&mut out, "cpu_limiter_pauses_total", "counter", "Times a process was stopped, by the duty cycle, global limiting or memory rules.");
+    let _ = writeln!(out, "cpu_limiter_pauses_total {}", status.pause_count);
+    metric(&mut out, "cpu_limiter_paused_processes", "gauge", "Processes currently stopped.");
+    let _ = writeln!(out, "cpu_limiter_paused_processes {}", status.currently_paused_pids.len());
+    metric(&mut out, "cpu_limiter_stopped_seconds_total", "counter", "Process time held stopped, summed over processes.");
+    let _ = writeln!(out, "cpu_limiter_stopped_seconds_total {}", status.throttled_ms as f64 / 1000.0);
+
+    metric(&mut out, "cpu_limiter_target_requested_percent", "gauge", "Share of each period a limited process may run.");
+    for (pid, percent) in &status.duty_cycle {
+        let _ = writeln!(out, "cpu_limiter_target_requested_percent{{{}}} {}", target_labels(snapshot, *pid), percent);
+    }
+    metric(&mut out, "cpu_limiter_target_measured_percent", "gauge", "Measured CPU of each limited process (100 = one core).");
+    for (pid, _) in &status.duty_cycle {
+        if let Some(process) = snapshot.process(*pid) {
+            let _ = writeln!(out, "cpu_limiter_target_measured_percent{{{}}} {}", target_labels(snapshot, *pid), process.cpu_usage);
+        }
+    }
+
+    metric(&mut out, "cpu_limiter_signal_errors_total", "counter", "Signals that could not be delivered, by errno.");
+    for (errno, count) in limiter::signal_errors() {
+        let _ = writeln!(out, "cpu_limiter_signal_errors_total{{errno=\"{}\"}} {}", escape(&errno), count);
+    }
+    out
+}
+
+fn metric(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn target_labels(snapshot: &Snapshot, pid: i32) -> String {
+    let name = snapshot.process(pid).map(|process| process.name.as_str()).unwrap_or_default();
+    format!("pid=\"{}\",name=\"{}\"", pid, escape(name))
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_exposition() {
+        let limiter = Limiter::new();
+        limiter.set_global(80);
+        let snapshot = Snapshot { per_core: vec![12.5, 80.0], ..Snapshot::default() };
+        let text = render(&limiter, &snapshot);
+        assert!(text.contains("# TYPE cpu_limiter_pauses_total counter\ncpu_limiter_pauses_total 0\n"));
+        assert!(text.contains("cpu_limiter_limit_percent{mode=\"global\"} 80\n"));
+        assert!(text.contains("cpu_limiter_core_usage_percent{core=\"0\"}"));
+        // Every sample line is `name{labels} value`
+        for line in text.lines().filter(|line| !line.starts_with('#')) {
+            let (_, value) = line.rsplit_once(' ').unwrap();
+            assert!(value.parse::<f64>().is_ok(), "{}", line);
+        }
+        assert_eq!(escape("a\"b\\c"), "a\\\"b\\\\c");
+    }
+}
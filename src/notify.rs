@@ -0,0 +1,134 @@
+use crate::limiter::{Limiter, LimiterMode};
+use std::ffi::OsStr;
+use std::io;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::time::Duration;
+
+/// Messages to systemd over `NOTIFY_SOCKET` (the `sd_notify` protocol), for
+/// units with `Type=notify` and `WatchdogSec=`.
+pub struct Notifier {
+    socket: UnixDatagram,
+    addr: SocketAddr,
+    watchdog: Option<Duration>,
+}
+
+impl Notifier {
+    /// `None` outside a notify-type unit.
+    pub fn from_env() -> Option<Self> {
+        let path = std::env::var_os("NOTIFY_SOCKET")?;
+        let watchdog = watchdog_interval(
+            std::env::var("WATCHDOG_USEC").ok().as_deref(),
+            std::env::var("WATCHDOG_PID").ok().as_deref(),
+        );
+        Self::new(&path, watchdog)
+            .inspect_err(|e| log::warn!("NOTIFY_SOCKET {}: {}", path.to_string_lossy(), e))
+            .ok()
+    }
+
+    fn new(path: &OsStr, watchdog: Option<Duration>) -> io::Result<Self> {
+        let addr = match path.as_encoded_bytes() {
+            // Abstract namespace, as systemd uses for user managers
+            #[cfg(target_os = "linux")]
+            [b'@', name @ ..] => {
+                use std::os::linux::net::SocketAddrExt;
+                SocketAddr::from_abstract_name(name)?
+            }
+            _ => SocketAddr::from_pathname(path)?,
+        };
+        Ok(Self {
+            socket: UnixDatagram::unbound()?,
+            addr,
+            watchdog,
+        })
+    }
+
+    /// How often systemd expects `WATCHDOG=1`, if the unit has a watchdog.
+    pub fn watchdog(&self) -> Option<Duration> {
+        self.watchdog
+    }
+
+    pub fn ready(&self, status: &str) {
+        self.send(&format!("READY=1\nSTATUS={}", status));
+    }
+
+    pub fn status(&self, status: &str) {
+        self.send(&format!("STATUS={}", status));
+    }
+
+    pub fn watchdog_ping(&self) {
+        self.send("WATCHDOG=1");
+    }
+
+    pub fn stopping(&self, status: &str) {
+        self.send(&format!("STOPPING=1\nSTATUS={}", status));
+    }
+
+    fn send(&self, message: &str) {
+        if let Err(e) = self.socket.send_to_addr(message.as_bytes(), &self.addr) {
+            log::warn!("sd_notify failed: {}", e);
+        }
+    }
+}
+
+/// `WATCHDOG_USEC`, unless `WATCHDOG_PID` says it is meant for another process.
+fn watchdog_interval(usec: Option<&str>, pid: Option<&str>) -> Option<Duration> {
+    if let Some(pid) = pid
+        && pid.parse::<u32>().ok() != Some(std::process::id())
+    {
+        return None;
+    }
+    match usec?.parse::<u64>() {
+        Ok(usec) if usec > 0 => Some(Duration::from_micros(usec)),
+        _ => None,
+    }
+}
+
+/// One line for `systemctl status`.
+pub fn status_text(limiter: &Limiter) -> String {
+    let state = limiter.get_state();
+    if !state.is_active {
+        return "Idle".to_string();
+    }
+    let status = limiter.get_status();
+    let mode = match state.mode {
+        LimiterMode::Targeted => "targeted",
+        LimiterMode::Global => "global",
+    };
+    let schedule = status.active_schedule.map(|name| format!(" (schedule {})", name)).unwrap_or_default();
+    format!(
+        "Limiting: {} {}%{}, {} paused",
+        mode,
+        state.limit_percentage,
+        schedule,
+        status.currently_paused_pids.len()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn test_messages_and_watchdog() {
+        let dir = TempDir::new("notify");
+        let path = dir.join("notify.sock");
+        let systemd = UnixDatagram::bind(&path).unwrap();
+
+        let notifier = Notifier::new(path.as_os_str(), None).unwrap();
+        let mut buf = [0; 256];
+        notifier.ready("Idle");
+        let len = systemd.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"READY=1\nSTATUS=Idle");
+        notifier.watchdog_ping();
+        let len = systemd.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"WATCHDOG=1");
+
+        let own = std::process::id().to_string();
+        assert_eq!(watchdog_interval(Some("30000000"), None), Some(Duration::from_secs(30)));
+        assert_eq!(watchdog_interval(Some("30000000"), Some(&own)), Some(Duration::from_secs(30)));
+        assert_eq!(watchdog_interval(Some("30000000"), Some("1")), None);
+        assert_eq!(watchdog_interval(Some("0"), None), None);
+        assert_eq!(watchdog_interval(None, None), None);
+    }
+}
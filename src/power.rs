@@ -0,0 +1,156 @@
+use crate::limiter::LimiterMode;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+pub const DEFAULT_POWER_SUPPLY_ROOT: &str = "/sys/class/power_supply";
+
+/// Power source as reported by the kernel.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PowerState {
+    pub on_battery: bool,
+    pub battery_percent: Option<u8>,
+}
+
+/// Replaces mode and limit of a configuration for one power source.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PowerVariant {
+    pub mode: Option<LimiterMode>, // None keeps the configured mode
+    pub limit_percentage: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatterySaver {
+    pub below_percent: u8,
+    pub variant: PowerVariant,
+}
+
+/// Per power source overrides; the battery saver wins over `on_battery`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PowerVariants {
+    pub on_ac: Option<PowerVariant>,
+    pub on_battery: Option<PowerVariant>,
+    pub battery_saver: Option<BatterySaver>,
+}
+
+impl PowerVariants {
+    pub fn is_empty(&self) -> bool {
+        self.on_ac.is_none() && self.on_battery.is_none() && self.battery_saver.is_none()
+    }
+
+    pub fn select(&self, power: PowerState) -> Option<(&'static str, PowerVariant)> {
+        if !power.on_battery {
+            return self.on_ac.map(|v| ("AC", v));
+        }
+        if let Some(saver) = self.battery_saver
+            && power.battery_percent.is_some_and(|p| p < saver.below_percent)
+        {
+            return Some(("battery saver", saver.variant));
+        }
+        self.on_battery.map(|v| ("battery", v))
+    }
+}
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    fs::read_to_string(path).ok().map(|s| s.trim().to_string())
+}
+
+/// Reads every supply under `root`. Returns `None` without a battery.
+///
+/// Running on battery means no external supply is online; if the machine has
+/// no external supply entries at all, the battery's own status decides.
+pub fn read_power_state(root: &Path) -> Option<PowerState> {
+    let mut external_supplies = 0;
+    let mut external_online = false;
+    let mut discharging = false;
+    let mut battery_percent = None;
+    let mut has_battery = false;
+
+    for entry in fs::read_dir(root).ok()?.flatten() {
+        let dir = entry.path();
+        match read_trimmed(&dir.join("type")).as_deref() {
+            Some("Battery") => {
+                // Peripherals (mice, headsets) report scope "Device"
+                if read_trimmed(&dir.join("scope")).as_deref() == Some("Device") {
+                    continue;
+                }
+                has_battery = true;
+                if read_trimmed(&dir.join("status")).as_deref() == Some("Discharging") {
+                    discharging = true;
+                }
+                if let Some(capacity) = read_trimmed(&dir.join("capacity")).and_then(|c| c.parse::<u8>().ok()) {
+                    battery_percent = Some(battery_percent.map_or(capacity, |p: u8| p.min(capacity)));
+                }
+            }
+            Some("Mains" | "USB" | "USB_C" | "USB_PD") => {
+                external_supplies += 1;
+                if read_trimmed(&dir.join("online")).as_deref() == Some("1") {
+                    external_online = true;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if !has_battery {
+        return None;
+    }
+    let on_battery = if external_supplies > 0 { !external_online } else { discharging };
+    Some(PowerState {
+        on_battery,
+        battery_percent,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    fn fixture(name: &str, supplies: &[(&str, &[(&str, &str)])]) -> TempDir {
+        let root = TempDir::new(&format!("power-{}", name));
+        for (supply, files) in supplies {
+            let dir = root.join(supply);
+            fs::create_dir_all(&dir).unwrap();
+            for (file, content) in *files {
+                fs::write(dir.join(file), format!("{}\n", content)).unwrap();
+            }
+        }
+        root
+    }
+
+    #[test]
+    fn test_laptop_on_battery() {
+        let root = fixture("battery", &[
+            ("AC", &[("type", "Mains"), ("online", "0")]),
+            ("BAT0", &[("type", "Battery"), ("status", "Discharging"), ("capacity", "15")]),
+            ("hidpp_battery_0", &[("type", "Battery"), ("scope", "Device"), ("capacity", "5")]),
+        ]);
+        let state = read_power_state(&root).unwrap();
+        assert_eq!(state, PowerState { on_battery: true, battery_percent: Some(15) });
+
+        let variants = PowerVariants {
+            on_ac: None,
+            on_battery: Some(PowerVariant { mode: Some(LimiterMode::Global), limit_percentage: 50 }),
+            battery_saver: Some(BatterySaver {
+                below_percent: 20,
+                variant: PowerVariant { mode: None, limit_percentage: 25 },
+            }),
+        };
+        assert_eq!(variants.select(state).unwrap().1.limit_percentage, 25);
+        let charged = PowerState { battery_percent: Some(80), ..state };
+        assert_eq!(variants.select(charged).unwrap().1.limit_percentage, 50);
+    }
+
+    #[test]
+    fn test_laptop_on_ac_and_desktop() {
+        let root = fixture("ac", &[
+            ("ADP1", &[("type", "Mains"), ("online", "1")]),
+            ("BAT0", &[("type", "Battery"), ("status", "Charging"), ("capacity", "60")]),
+        ]);
+        assert!(!read_power_state(&root).unwrap().on_battery);
+
+        let root = fixture("desktop", &[("ADP1", &[("type", "Mains"), ("online", "1")])]);
+        assert_eq!(read_power_state(&root), None);
+    }
+}
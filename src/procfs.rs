@@ -0,0 +1,335 @@
+use crate::cgroup;
+use crate::sampler::{Process, Snapshot};
+use chrono::Local;
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+/// CPU time from one `cpu` line of `/proc/stat`, in clock ticks.
+#[derive(Clone, Copy, Default)]
+struct CpuTimes {
+    total: u64,
+    idle: u64, // idle + iowait
+}
+
+impl CpuTimes {
+    fn usage_since(self, previous: CpuTimes) -> f32 {
+        let total = self.total.saturating_sub(previous.total);
+        let idle = self.idle.saturating_sub(previous.idle);
+        if total == 0 { 0.0 } else { total.saturating_sub(idle) as f32 / total as f32 * 100.0 }
+    }
+}
+
+const CGROUP_REFRESH: u64 = 10; // Samples between rereading the cgroups of known processes
+
+/// What is kept of a process between samples.
+struct Tracked {
+    ticks: u64, // utime + stime
+    process: Process,
+}
+
+/// Reads `/proc` directly: `stat`, `meminfo` and each `[pid]/stat`, into
+/// buffers kept between samples. Only the fields the limiter uses are parsed,
+/// and CPU usage comes from the ticks since the previous sample.
+pub struct Reader {
+    root: PathBuf,
+    page_size: u64,
+    buf: String,
+    cgroup_buf: String,
+    path: PathBuf,
+    samples: u64,
+    cpu: CpuTimes, // All cores
+    cores: Vec<CpuTimes>,
+    processes: HashMap<i32, Tracked>,
+}
+
+impl Reader {
+    pub fn new(root: PathBuf) -> Self {
+        // SAFETY: sysconf only reads a system constant.
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+        Self {
+            root,
+            page_size: u64::try_from(page_size).unwrap_or(4096),
+            buf: String::new(),
+            cgroup_buf: String::new(),
+            path: PathBuf::new(),
+            samples: 0,
+            cpu: CpuTimes::default(),
+            cores: Vec::new(),
+            processes: HashMap::new(),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Fails only if the system-wide files can't be read; processes that
+    /// exit while being read are left out. Cgroups are read for new processes,
+    /// and now and then for the rest, as processes can be moved.
+    pub fn sample(&mut self) -> io::Result<Snapshot> {
+        let reread_cgroups = self.samples.is_multiple_of(CGROUP_REFRESH);
+        self.samples += 1;
+        read(&mut self.path, &self.root, None, "stat", &mut self.buf)?;
+        let (cpu, cores) = parse_cpu_times(&self.buf);
+        let cpu_percent = cpu.usage_since(self.cpu);
+        let per_core = cores
+            .iter()
+            .enumerate()
+            .map(|(index, times)| times.usage_since(self.cores.get(index).copied().unwrap_or_default()))
+            .collect();
+        // Ticks of one core over the interval, as process usage is per core
+        let core_ticks = cpu.total.saturating_sub(self.cpu.total) as f32 / cores.len().max(1) as f32;
+        self.cpu = cpu;
+        self.cores = cores;
+
+        read(&mut self.path, &self.root, None, "meminfo", &mut self.buf)?;
+        let (memory_total, memory_available) = parse_meminfo(&self.buf);
+
+        let mut seen = Vec::with_capacity(self.processes.len());
+        for entry in fs::read_dir(&self.root)?.flatten() {
+            let dir = entry.file_name();
+            let Some(pid) = dir.to_str().and_then(|name| name.parse::<i32>().ok()) else {
+                continue;
+            };
+            if read(&mut self.path, &self.root, Some(&dir), "stat", &mut self.buf).is_err() {
+                continue;
+            }
+            let Some(stat) = parse_pid_stat(&self.buf) else {
+                continue;
+            };
+            seen.push(pid);
+            let known = self.processes.get(&pid).is_some_and(|tracked| tracked.process.start_time == stat.start_time);
+            let cgroup = (reread_cgroups || !known).then(|| {
+                read(&mut self.path, &self.root, Some(&dir), "cgroup", &mut self.cgroup_buf)
+                    .ok()
+                    .and_then(|()| cgroup::parse(&self.cgroup_buf))
+            });
+
+            let memory = stat.rss_pages * self.page_size;
+            match self.processes.get_mut(&pid) {
+                Some(tracked) if tracked.process.start_time == stat.start_time => {
+                    let ticks = stat.ticks.saturating_sub(tracked.ticks);
+                    tracked.ticks = stat.ticks;
+                    let process = &mut tracked.process;
+                    process.cpu_usage = if core_ticks > 0.0 { ticks as f32 / core_ticks * 100.0 } else { 0.0 };
+                    process.memory = memory;
+                    process.parent = stat.parent;
+                    // Changes on exec
+                    if process.name != stat.name {
+                        process.name = stat.name.to_string();
+                    }
+                    if let Some(cgroup) = cgroup {
+                        process.cgroup = cgroup;
+                    }
+                }
+                _ => {
+                    let uid = fs::metadata(entry.path()).ok().map(|m| m.uid());
+                    let process = Process {
+                        pid,
+                        parent: stat.parent,
+                        name: stat.name.to_string(),
+                        cpu_usage: 0.0, // Nothing to compare with yet
+                        memory,
+                        uid,
+                        start_time: stat.start_time,
+                        cgroup: cgroup.flatten(),
+                    };
+                    self.processes.insert(pid, Tracked { ticks: stat.ticks, process });
+                }
+            }
+        }
+        if seen.len() != self.processes.len() {
+            seen.sort_unstable();
+            self.processes.retain(|pid, _| seen.binary_search(pid).is_ok());
+        }
+
+        Ok(Snapshot {
+            time: Local::now(),
+            cpu_percent,
+            per_core,
+            memory_used: memory_total.saturating_sub(memory_available),
+            memory_total,
+            memory_available,
+            processes: self.processes.iter().map(|(pid, tracked)| (*pid, tracked.process.clone())).collect(),
+        })
+    }
+}
+
+/// Reads `root/[dir/]file` into `buf`, building the name in `path`.
+fn read(path: &mut PathBuf, root: &Path, dir: Option<&OsStr>, file: &str, buf: &mut String) -> io::Result<()> {
+    let name = path.as_mut_os_string();
+    name.clear();
+    name.push(root.as_os_str());
+    for part in dir.into_iter().chain([OsStr::new(file)]) {
+        name.push("/");
+        name.push(part);
+    }
+    buf.clear();
+    File::open(path)?.read_to_string(buf)?;
+    Ok(())
+}
+
+/// The aggregate `cpu` line and the `cpuN` lines.
+fn parse_cpu_times(stat: &str) -> (CpuTimes, Vec<CpuTimes>) {
+    let mut all = CpuTimes::default();
+    let mut cores = Vec::new();
+    for line in stat.lines().take_while(|line| line.starts_with("cpu")) {
+        let mut fields = line.split_ascii_whitespace();
+        let label = fields.next().unwrap_or_default();
+        let mut times = CpuTimes::default();
+        // user nice system idle iowait irq softirq steal; guest time is already in user
+        for (index, value) in fields.take(8).enumerate() {
+            let value: u64 = value.parse().unwrap_or(0);
+            times.total += value;
+            if index == 3 || index == 4 {
+                times.idle += value;
+            }
+        }
+        if label == "cpu" {
+            all = times;
+        } else {
+            cores.push(times);
+        }
+    }
+    (all, cores)
+}
+
+/// `MemTotal` and `MemAvailable`, in bytes.
+fn parse_meminfo(meminfo: &str) -> (u64, u64) {
+    let (mut total, mut available) = (0, 0);
+    for line in meminfo.lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let kib = || value.trim().trim_end_matches("kB").trim().parse::<u64>().unwrap_or(0) * 1024;
+        match key {
+            "MemTotal" => total = kib(),
+            "MemAvailable" => available = kib(),
+            _ => {}
+        }
+    }
+    (total, available)
+}
+
+struct PidStat<'a> {
+    name: &'a str,
+    parent: Option<i32>,
+    ticks: u64,
+    start_time: u64,
+    rss_pages: u64,
+}
+
+/// Parent of `pid` straight from `<root>/<pid>/stat`, for processes newer
+/// than the last snapshot.
+pub fn read_parent(root: &Path, pid: i32) -> Option<i32> {
+    let stat = fs::read_to_string(root.join(pid.to_string()).join("stat")).ok()?;
+    parse_pid_stat(&stat)?.parent
+}
+
+/// `pid (comm) state ppid ...`; comm may hold spaces and parentheses, so it
+/// runs to the last `)`.
+fn parse_pid_stat(stat: &str) -> Option<PidStat<'_>> {
+    let open = stat.find('(')?;
+    let close = stat.rfind(')')?;
+    let name = stat.get(open + 1..close)?;
+    let mut fields = stat.get(close + 1..)?.split_ascii_whitespace();
+    // Field N as numbered in proc(5), taken in increasing order
+    let mut taken = 2;
+    let mut field = |n: usize| {
+        let value = fields.nth(n - taken - 1)?.parse::<u64>().ok();
+        taken = n;
+        value
+    };
+    let parent = field(4)?;
+    let ticks = field(14)? + field(15)?;
+    Some(PidStat {
+        name,
+        parent: (parent > 0).then_some(parent as i32),
+        ticks,
+        start_time: field(22)?,
+        rss_pages: field(24)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    fn pid_stat(pid: i32, name: &str, ppid: i32, utime: u64, start_time: u64) -> String {
+        format!(
+            "{} ({}) S {} {} {} 0 -1 4194304 100 0 0 0 {} 5 0 0 20 0 1 0 {} 1000000 256 18446744073709551615\n",
+            pid, name, ppid, pid, pid, utime, start_time
+        )
+    }
+
+    /// Two cores, each with `user` and `idle` ticks so far.
+    fn write_fixture(root: &Path, user: u64, idle: u64, processes: &[(i32, String)]) {
+        let stat = format!(
+            "cpu  {} 0 0 {} 0 0 0 0 0 0\ncpu0 {} 0 0 {} 0 0 0 0 0 0\ncpu1 {} 0 0 {} 0 0 0 0 0 0\nintr 1\n",
+            user * 2,
+            idle * 2,
+            user,
+            idle,
+            user,
+            idle
+        );
+        fs::write(root.join("stat"), stat).unwrap();
+        fs::write(root.join("meminfo"), "MemTotal:       8000 kB\nMemFree:        1000 kB\nMemAvailable:   6000 kB\n").unwrap();
+        for entry in fs::read_dir(root).unwrap().flatten() {
+            if entry.file_name().to_string_lossy().parse::<i32>().is_ok() {
+                fs::remove_dir_all(entry.path()).unwrap();
+            }
+        }
+        for (pid, stat) in processes {
+            fs::create_dir_all(root.join(pid.to_string())).unwrap();
+            fs::write(root.join(pid.to_string()).join("stat"), stat).unwrap();
+            fs::write(root.join(pid.to_string()).join("cgroup"), format!("0::/test.slice/{}.service\n", pid)).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_fixture_deltas() {
+        let root = TempDir::new("procfs");
+        let mut reader = Reader::new(root.to_path_buf());
+
+        write_fixture(&root, 0, 1000, &[(1, pid_stat(1, "init", 0, 0, 1)), (42, pid_stat(42, "a (b) c", 1, 100, 50))]);
+        let first = reader.sample().unwrap();
+        assert_eq!(first.memory_total, 8000 * 1024);
+        assert_eq!(first.memory_used, 2000 * 1024);
+        let process = first.process(42).unwrap();
+        assert_eq!((process.name.as_str(), process.parent, process.cpu_usage), ("a (b) c", Some(1), 0.0));
+        assert_eq!(process.memory, 256 * reader.page_size);
+        assert_eq!(process.cgroup.as_deref(), Some("/test.slice/42.service"));
+        assert_eq!(first.process(1).unwrap().parent, None);
+
+        // 1000 ticks per core: 42 used 500 of them, one core half busy overall
+        write_fixture(
+            &root,
+            250,
+            1750,
+            &[(1, pid_stat(1, "init", 0, 0, 1)), (42, pid_stat(42, "renamed", 1, 600, 50)), (43, pid_stat(43, "new", 42, 900, 60))],
+        );
+        let second = reader.sample().unwrap();
+        assert_eq!(second.cpu_percent, 25.0);
+        assert_eq!(second.per_core, vec![25.0, 25.0]);
+        let process = second.process(42).unwrap();
+        assert_eq!((process.name.as_str(), process.cpu_usage), ("renamed", 50.0));
+        assert_eq!(second.process(43).unwrap().cpu_usage, 0.0);
+        assert_eq!(read_parent(&root, 43), Some(42));
+        assert_eq!(read_parent(&root, 1), None);
+
+        // 42 exited and its pid was reused; 43 is gone
+        write_fixture(&root, 500, 2500, &[(42, pid_stat(42, "reused", 1, 10, 70))]);
+        let third = reader.sample().unwrap();
+        assert_eq!(third.processes.len(), 1);
+        assert_eq!(third.process(42).unwrap().cpu_usage, 0.0);
+
+        fs::remove_file(root.join("stat")).unwrap();
+        assert!(reader.sample().is_err());
+    }
+}
@@ -0,0 +1,265 @@
+use chrono::{DateTime, Local};
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use sysinfo::{ProcessRefreshKind, ProcessesToUpdate, System, UpdateKind};
+
+pub const SAMPLE_INTERVAL: Duration = Duration::from_millis(500);
+const DEFAULT_PROC_ROOT: &str = "/proc";
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Process {
+    pub pid: i32,
+    pub parent: Option<i32>,
+    pub name: String,
+    pub cpu_usage: f32, // 100 = one core
+    pub memory: u64,    // Resident bytes
+    pub uid: Option<u32>,
+    pub start_time: u64, // Tells a reused pid from the process that had it; units depend on the source
+    pub cgroup: Option<String>, // Path in the cgroup hierarchy, Linux only
+}
+
+/// The system as of one sample; never changes once published.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    pub time: DateTime<Local>,
+    pub cpu_percent: f32, // Average over all cores
+    pub per_core: Vec<f32>,
+    pub memory_used: u64,
+    pub memory_total: u64,
+    pub memory_available: u64,
+    pub processes: HashMap<i32, Process>,
+}
+
+impl Default for Snapshot {
+    fn default() -> Self {
+        Self {
+            time: Local::now(),
+            cpu_percent: 0.0,
+            per_core: Vec::new(),
+            memory_used: 0,
+            memory_total: 0,
+            memory_available: 0,
+            processes: HashMap::new(),
+        }
+    }
+}
+
+impl Snapshot {
+    fn from_system(system: &System) -> Self {
+        let processes = system
+            .processes()
+            .iter()
+            .map(|(pid, process)| {
+                let pid = pid.as_u32() as i32;
+                let process = Process {
+                    pid,
+                    parent: process.parent().map(|p| p.as_u32() as i32),
+                    name: process.name().to_string_lossy().to_string(),
+                    cpu_usage: process.cpu_usage(),
+                    memory: process.memory(),
+                    uid: process.user_id().map(|uid| **uid),
+                    start_time: process.start_time(),
+                    cgroup: None,
+                };
+                (pid, process)
+            })
+            .collect();
+        Self {
+            time: Local::now(),
+            cpu_percent: system.global_cpu_usage(),
+            per_core: system.cpus().iter().map(|cpu| cpu.cpu_usage()).collect(),
+            memory_used: system.used_memory(),
+            memory_total: system.total_memory(),
+            memory_available: system.available_memory(),
+            processes,
+        }
+    }
+
+    pub fn process(&self, pid: i32) -> Option<&Process> {
+        self.processes.get(&pid)
+    }
+
+    /// Processes sorted by CPU usage, busiest first.
+    pub fn by_cpu(&self) -> Vec<&Process> {
+        let mut processes: Vec<&Process> = self.processes.values().collect();
+        processes.sort_by(|a, b| b.cpu_usage.partial_cmp(&a.cpu_usage).unwrap_or(std::cmp::Ordering::Equal));
+        processes
+    }
+}
+
+type Listener = Box<dyn Fn() + Send>;
+
+/// Where snapshots come from: `/proc` read directly on Linux, else sysinfo.
+enum Source {
+    System(Box<System>),
+    #[cfg(target_os = "linux")]
+    Procfs(crate::procfs::Reader),
+}
+
+impl Source {
+    #[cfg(target_os = "linux")]
+    fn new(proc_root: &Path) -> Self {
+        Source::Procfs(crate::procfs::Reader::new(proc_root.to_path_buf()))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn new(_proc_root: &Path) -> Self {
+        Source::System(Box::default())
+    }
+
+    fn sample(&mut self) -> Snapshot {
+        match self {
+            Source::System(system) => {
+                refresh(system);
+                Snapshot::from_system(system)
+            }
+            #[cfg(target_os = "linux")]
+            Source::Procfs(reader) => match reader.sample() {
+                Ok(snapshot) => snapshot,
+                Err(e) => {
+                    log::warn!("reading {} failed ({}), falling back to sysinfo", reader.root().display(), e);
+                    *self = Source::System(Box::default());
+                    self.sample()
+                }
+            },
+        }
+    }
+}
+
+/// Scans the system on its own thread every `SAMPLE_INTERVAL`, so the
+/// limiter, the window and the tray share one process table.
+pub struct Sampler {
+    latest: Arc<Mutex<Arc<Snapshot>>>,
+    listeners: Arc<Mutex<Vec<Listener>>>, // Called after each new snapshot
+    proc_root: Arc<Mutex<PathBuf>>,
+    stop: Arc<AtomicBool>,
+    thread: Mutex<Option<thread::JoinHandle<()>>>,
+}
+
+impl Sampler {
+    pub fn new() -> Self {
+        Self {
+            latest: Arc::new(Mutex::new(Arc::new(Snapshot::default()))),
+            listeners: Arc::new(Mutex::new(Vec::new())),
+            proc_root: Arc::new(Mutex::new(PathBuf::from(DEFAULT_PROC_ROOT))),
+            stop: Arc::new(AtomicBool::new(false)),
+            thread: Mutex::new(None),
+        }
+    }
+
+    /// Starts sampling, unless it already runs.
+    pub fn start(&self) {
+        let mut thread = self.thread.lock();
+        if thread.is_some() {
+            return;
+        }
+        self.stop.store(false, Ordering::Relaxed);
+        let latest = self.latest.clone();
+        let listeners = self.listeners.clone();
+        let proc_root = self.proc_root.clone();
+        let stop = self.stop.clone();
+        *thread = Some(thread::spawn(move || {
+            let mut root = proc_root.lock().clone();
+            let mut source = Source::new(&root);
+            // CPU usage is a delta, so the first sample only sets the baseline
+            source.sample();
+            let mut next = Instant::now() + sysinfo::MINIMUM_CPU_UPDATE_INTERVAL;
+            loop {
+                while let Some(wait) = next.checked_duration_since(Instant::now()) {
+                    if stop.load(Ordering::Relaxed) {
+                        return;
+                    }
+                    thread::park_timeout(wait);
+                }
+                if stop.load(Ordering::Relaxed) {
+                    return;
+                }
+                next = Instant::now() + SAMPLE_INTERVAL;
+                let current = proc_root.lock().clone();
+                if current != root {
+                    source = Source::new(&current);
+                    root = current;
+                }
+                *latest.lock() = Arc::new(source.sample());
+                for listener in listeners.lock().iter() {
+                    listener();
+                }
+            }
+        }));
+    }
+
+    /// The newest snapshot; one without processes until the first sample is taken.
+    pub fn latest(&self) -> Arc<Snapshot> {
+        self.latest.lock().clone()
+    }
+
+    /// Reads processes from a procfs mounted elsewhere, such as a test fixture.
+    pub fn set_proc_root(&self, root: PathBuf) {
+        *self.proc_root.lock() = root;
+    }
+
+    pub fn proc_root(&self) -> PathBuf {
+        self.proc_root.lock().clone()
+    }
+
+    pub fn subscribe(&self, listener: impl Fn() + Send + 'static) {
+        self.listeners.lock().push(Box::new(listener));
+    }
+
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.thread.lock().take() {
+            handle.thread().unpark();
+            let _ = handle.join();
+        }
+    }
+}
+
+fn refresh(system: &mut System) {
+    system.refresh_cpu_all();
+    system.refresh_memory();
+    // Owners are needed for the audit log, but never change
+    let kind = ProcessRefreshKind::nothing()
+        .with_cpu()
+        .with_memory()
+        .with_user(UpdateKind::OnlyIfNotSet);
+    system.refresh_processes_specifics(ProcessesToUpdate::All, true, kind);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_sampler_publishes_snapshots() {
+        let sampler = Sampler::new();
+        assert!(sampler.latest().processes.is_empty());
+
+        let (sender, samples) = mpsc::channel();
+        sampler.subscribe(move || {
+            let _ = sender.send(());
+        });
+        sampler.start();
+        samples.recv_timeout(Duration::from_secs(5)).unwrap();
+        let first = sampler.latest();
+        let myself = first.process(std::process::id() as i32).expect("own process in the snapshot");
+        assert!(!myself.name.is_empty());
+        assert!(!first.per_core.is_empty());
+        assert!(first.memory_total >= first.memory_used && first.memory_total > 0);
+
+        // Each sample is a new snapshot, the old one stays as it was
+        samples.recv_timeout(Duration::from_secs(5)).unwrap();
+        let second = sampler.latest();
+        assert!(!Arc::ptr_eq(&first, &second));
+        assert!(second.time >= first.time);
+
+        sampler.stop();
+        assert!(sampler.thread.lock().is_none());
+    }
+}
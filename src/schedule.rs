@@ -0,0 +1,208 @@
+use crate::limiter::LimiterConfig;
+use anyhow::{Context, bail};
+use chrono::{Datelike, Local, Timelike, Weekday};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+const DAY_NAMES: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+const FULL_DAY_NAMES: [&str; 7] = ["monday", "tuesday", "wednesday", "thursday", "friday", "saturday", "sunday"];
+const ALL_DAYS: u8 = 0b111_1111;
+const MINUTES_PER_DAY: u32 = 24 * 60;
+
+/// Days of the week plus a daily time window, e.g. `Mon-Fri 09:00-18:00`.
+///
+/// Days accept `*`/`daily`, `weekdays`, `weekends`, names, ranges (`Mon-Fri`
+/// or `Mon..Fri`) and comma lists. The window is optional (whole day) and may
+/// wrap past midnight (`22:00-06:00`), in which case it belongs to the day it
+/// starts on.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct CalendarSpec {
+    days: u8, // Bit 0 = Monday
+    start: u32, // Minutes since midnight
+    end: u32,
+    source: String,
+}
+
+impl CalendarSpec {
+    pub fn contains(&self, weekday: Weekday, minute: u32) -> bool {
+        let today = 1 << weekday.num_days_from_monday();
+        let yesterday = 1 << weekday.pred().num_days_from_monday();
+        if self.start < self.end {
+            self.days & today != 0 && (self.start..self.end).contains(&minute)
+        } else {
+            // Wraps midnight (or start == end: the whole day)
+            (self.days & today != 0 && minute >= self.start)
+                || (self.days & yesterday != 0 && minute < self.end)
+        }
+    }
+
+    pub fn contains_now(&self) -> bool {
+        let now = Local::now();
+        self.contains(now.weekday(), now.hour() * 60 + now.minute())
+    }
+}
+
+impl FromStr for CalendarSpec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let mut parts = s.split_whitespace();
+        let Some(first) = parts.next() else {
+            bail!("empty calendar expression");
+        };
+
+        let (days, window) = if first.contains(':') {
+            (ALL_DAYS, Some(first))
+        } else {
+            (parse_days(first)?, parts.next())
+        };
+        if let Some(extra) = parts.next() {
+            bail!("unexpected '{}'", extra);
+        }
+
+        let (start, end) = match window {
+            Some(window) => {
+                let (start, end) = window
+                    .split_once('-')
+                    .with_context(|| format!("expected HH:MM-HH:MM, got '{}'", window))?;
+                (parse_time(start)?, parse_time(end)?)
+            }
+            None => (0, 0),
+        };
+
+        Ok(Self {
+            days,
+            start,
+            end,
+            source: s.trim().to_string(),
+        })
+    }
+}
+
+impl fmt::Display for CalendarSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+// Serialized as the text it was parsed from
+impl TryFrom<String> for CalendarSpec {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> anyhow::Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<CalendarSpec> for String {
+    fn from(spec: CalendarSpec) -> Self {
+        spec.source
+    }
+}
+
+fn parse_day(name: &str) -> anyhow::Result<u32> {
+    let name = name.to_ascii_lowercase();
+    (0..7)
+        .find(|&index| name == DAY_NAMES[index] || name == FULL_DAY_NAMES[index])
+        .map(|index| index as u32)
+        .with_context(|| format!("unknown day '{}'", name))
+}
+
+fn parse_days(s: &str) -> anyhow::Result<u8> {
+    match s.to_ascii_lowercase().as_str() {
+        "*" | "daily" => return Ok(ALL_DAYS),
+        "weekdays" => return Ok(0b001_1111),
+        "weekends" => return Ok(0b110_0000),
+        _ => {}
+    }
+
+    let mut days = 0u8;
+    for item in s.split(',') {
+        let range = item.split_once("..").or_else(|| item.split_once('-'));
+        match range {
+            Some((from, to)) => {
+                let (from, to) = (parse_day(from)?, parse_day(to)?);
+                let mut day = from;
+                loop {
+                    days |= 1 << day;
+                    if day == to {
+                        break;
+                    }
+                    day = (day + 1) % 7;
+                }
+            }
+            None => days |= 1 << parse_day(item)?,
+        }
+    }
+    Ok(days)
+}
+
+fn parse_time(s: &str) -> anyhow::Result<u32> {
+    let (hours, minutes) = s.split_once(':').unwrap_or((s, "0"));
+    let hours: u32 = hours.parse().with_context(|| format!("invalid time '{}'", s))?;
+    let minutes: u32 = minutes.parse().with_context(|| format!("invalid time '{}'", s))?;
+    if hours > 24 || minutes > 59 || hours * 60 + minutes > MINUTES_PER_DAY {
+        bail!("invalid time '{}'", s);
+    }
+    Ok((hours * 60 + minutes) % MINUTES_PER_DAY)
+}
+
+/// A named limiter configuration that replaces the base one while `when` matches.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Schedule {
+    pub name: String,
+    pub when: CalendarSpec,
+    pub config: LimiterConfig,
+}
+
+/// First schedule matching right now; earlier entries win.
+pub fn active(schedules: &[Schedule]) -> Option<&Schedule> {
+    schedules.iter().find(|schedule| schedule.when.contains_now())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_weekday_hours() {
+        let spec: CalendarSpec = "Mon-Fri 09:00-18:00".parse().unwrap();
+        assert!(spec.contains(Weekday::Mon, 9 * 60));
+        assert!(spec.contains(Weekday::Fri, 17 * 60 + 59));
+        assert!(!spec.contains(Weekday::Fri, 18 * 60));
+        assert!(!spec.contains(Weekday::Sat, 12 * 60));
+        assert_eq!(spec.to_string(), "Mon-Fri 09:00-18:00");
+    }
+
+    #[test]
+    fn test_overnight_and_lists() {
+        let spec: CalendarSpec = "Fri,Sat 22:00-06:00".parse().unwrap();
+        assert!(spec.contains(Weekday::Fri, 23 * 60));
+        assert!(spec.contains(Weekday::Sat, 5 * 60));
+        assert!(spec.contains(Weekday::Sun, 5 * 60));
+        assert!(!spec.contains(Weekday::Sun, 23 * 60));
+        assert!(!spec.contains(Weekday::Fri, 5 * 60));
+
+        let spec: CalendarSpec = "Sat..Mon".parse().unwrap();
+        assert!(spec.contains(Weekday::Sun, 0));
+        assert!(spec.contains(Weekday::Mon, MINUTES_PER_DAY - 1));
+        assert!(!spec.contains(Weekday::Tue, 12 * 60));
+
+        let spec: CalendarSpec = "08:30-12:00".parse().unwrap();
+        assert!(spec.contains(Weekday::Wed, 8 * 60 + 30));
+    }
+
+    #[test]
+    fn test_invalid_expressions() {
+        assert!("".parse::<CalendarSpec>().is_err());
+        assert!("Funday 09:00-10:00".parse::<CalendarSpec>().is_err());
+        assert!("Monkey 09:00-10:00".parse::<CalendarSpec>().is_err());
+        assert!("sunshine".parse::<CalendarSpec>().is_err());
+        assert!("Monday-Friday 09:00-10:00".parse::<CalendarSpec>().is_ok());
+        assert!("Mon 25:00-26:00".parse::<CalendarSpec>().is_err());
+        assert!("Mon 09:00".parse::<CalendarSpec>().is_err());
+        assert!("Mon 09:00-10:00 extra".parse::<CalendarSpec>().is_err());
+    }
+}
@@ -0,0 +1,101 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+pub const DEFAULT_THERMAL_ROOT: &str = "/sys/class/thermal";
+
+/// Throttles the top CPU consumers while the selected zones run hot.
+///
+/// Above `threshold_c` one more consumer is duty-cycled at `throttle_percent`
+/// per check; below `threshold_c - hysteresis_c` one is released per check.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ThermalTrigger {
+    pub zones: Vec<String>, // Zone dir or type, e.g. "thermal_zone0" or "x86_pkg_temp"; empty = all
+    pub threshold_c: f32,
+    pub hysteresis_c: f32,
+    pub throttle_percent: u32,
+}
+
+impl Default for ThermalTrigger {
+    fn default() -> Self {
+        Self {
+            zones: Vec::new(),
+            threshold_c: 85.0,
+            hysteresis_c: 5.0,
+            throttle_percent: 50,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ThermalZone {
+    pub name: String, // thermal_zoneN
+    pub kind: String, // Contents of `type`
+    pub temperature_c: Option<f32>,
+}
+
+impl ThermalZone {
+    pub fn matches(&self, selector: &str) -> bool {
+        self.name == selector || self.kind == selector
+    }
+}
+
+pub fn list_zones(root: &Path) -> Vec<ThermalZone> {
+    let Ok(entries) = fs::read_dir(root) else {
+        return Vec::new();
+    };
+    let mut zones: Vec<_> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !name.starts_with("thermal_zone") {
+                return None;
+            }
+            let dir = entry.path();
+            let kind = fs::read_to_string(dir.join("type")).unwrap_or_default().trim().to_string();
+            // Millidegrees Celsius
+            let temperature_c = fs::read_to_string(dir.join("temp"))
+                .ok()
+                .and_then(|t| t.trim().parse::<i64>().ok())
+                .map(|milli| milli as f32 / 1000.0);
+            Some(ThermalZone { name, kind, temperature_c })
+        })
+        .collect();
+    zones.sort_by_key(|zone| zone.name.trim_start_matches("thermal_zone").parse::<u32>().unwrap_or(u32::MAX));
+    zones
+}
+
+/// Hottest reading among the selected zones (all zones when `selectors` is empty).
+pub fn read_temperature(root: &Path, selectors: &[String]) -> Option<f32> {
+    list_zones(root)
+        .into_iter()
+        .filter(|zone| selectors.is_empty() || selectors.iter().any(|s| zone.matches(s)))
+        .filter_map(|zone| zone.temperature_c)
+        .reduce(f32::max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn test_read_selected_zones() {
+        let root = TempDir::new("thermal");
+        for (zone, kind, temp) in [("thermal_zone0", "acpitz", "45000"), ("thermal_zone1", "x86_pkg_temp", "91500")] {
+            let dir = root.join(zone);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("type"), format!("{}\n", kind)).unwrap();
+            fs::write(dir.join("temp"), format!("{}\n", temp)).unwrap();
+        }
+        fs::create_dir_all(root.join("cooling_device0")).unwrap();
+
+        let zones = list_zones(&root);
+        assert_eq!(zones.len(), 2);
+        assert_eq!(zones[1].kind, "x86_pkg_temp");
+        assert_eq!(read_temperature(&root, &[]), Some(91.5));
+        assert_eq!(read_temperature(&root, &["acpitz".to_string()]), Some(45.0));
+        assert_eq!(read_temperature(&root, &["thermal_zone1".to_string()]), Some(91.5));
+        assert_eq!(read_temperature(&root, &["missing".to_string()]), None);
+    }
+}
@@ -1,28 +1,136 @@
-use crate::limiter::Limiter;
+use crate::audit;
+use crate::cgroup::{self, Group, Kind};
+#[cfg(target_os = "linux")]
+use crate::autostart::{Autostart, Method};
+use crate::cli::{self, Command};
+use crate::config::{self, AuditSettings, Config, ConfigFile, LimiterSettings, Profile, RefreshSettings, SelectedProcess, TraySettings, UiSettings};
+use crate::control::{ControlServer, InstanceCommand};
+use crate::http::{self, HttpServer};
+use crate::indicator::{self, Activity};
+use crate::history::{self, Format, Sample};
+use crate::limiter::{self, EscalationPolicy, EscalationStep, Limiter, LimiterConfig, LimiterMode, MemoryAction, MemoryRule, MemoryTrigger};
+use crate::power::{BatterySaver, PowerVariant, PowerVariants};
+use crate::schedule::{CalendarSpec, Schedule};
+use crate::thermal::{self, ThermalTrigger, ThermalZone};
 use eframe::egui;
 use eframe::egui::scroll_area::ScrollBarVisibility;
 use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver};
 use std::time::{Duration, Instant};
-use std::collections::VecDeque;
-use sysinfo::System;
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
 use tray_icon::{
-    MouseButton, MouseButtonState, TrayIcon, TrayIconEvent,
-    menu::{MenuEvent, MenuId},
+    Icon, MouseButton, MouseButtonState, TrayIcon, TrayIconEvent,
+    menu::{CheckMenuItem, MenuEvent, MenuId, MenuItem, Submenu},
 };
 
+const PROFILE_MENU_PREFIX: &str = "profile:";
+const LIMIT_MENU_PREFIX: &str = "limit:";
+const RESUME_MENU_PREFIX: &str = "resume:";
+const TOP_MENU_PREFIX: &str = "top:";
+pub const LIMIT_PRESETS: [u32; 3] = [25, 50, 75];
+const TOP_MENU_COUNT: usize = 5;
+const TOP_MENU_INTERVAL: Duration = Duration::from_secs(3); // Slow enough to aim at an entry
+
+/// Quick actions offered for each of the top consumers in the tray.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum QuickAction {
+    Limit(u32),
+    Pause,
+    Protect,
+}
+
+impl QuickAction {
+    const ALL: [QuickAction; 4] = [QuickAction::Limit(25), QuickAction::Limit(50), QuickAction::Pause, QuickAction::Protect];
+
+    fn label(self) -> String {
+        match self {
+            QuickAction::Limit(percent) => format!("Limit to {}%", percent),
+            QuickAction::Pause => "Pause".to_string(),
+            QuickAction::Protect => "Protect".to_string(),
+        }
+    }
+
+    /// `top:<pid>:<action>`
+    fn menu_id(self, pid: i32) -> MenuId {
+        let action = match self {
+            QuickAction::Limit(percent) => format!("limit{}", percent),
+            QuickAction::Pause => "pause".to_string(),
+            QuickAction::Protect => "protect".to_string(),
+        };
+        MenuId::new(format!("{}{}:{}", TOP_MENU_PREFIX, pid, action))
+    }
+
+    fn parse(id: &str) -> Option<(i32, QuickAction)> {
+        let (pid, action) = id.strip_prefix(TOP_MENU_PREFIX)?.split_once(':')?;
+        let action = match action {
+            "pause" => QuickAction::Pause,
+            "protect" => QuickAction::Protect,
+            limit => QuickAction::Limit(limit.strip_prefix("limit")?.parse().ok()?),
+        };
+        Some((pid.parse().ok()?, action))
+    }
+}
+
+/// Tray menu entries the app reacts to or keeps up to date.
+pub struct TrayMenu {
+    pub quit_id: MenuId,
+    pub toggle: MenuItem,
+    pub targeted: CheckMenuItem,
+    pub global: CheckMenuItem,
+    pub presets: Vec<CheckMenuItem>, // One per `LIMIT_PRESETS` entry
+    pub paused: Submenu,
+    pub top: Submenu,
+    pub profiles: Submenu,
+}
+
+impl TrayMenu {
+    pub fn limit_id(percent: u32) -> MenuId {
+        MenuId::new(format!("{}{}", LIMIT_MENU_PREFIX, percent))
+    }
+}
+
 pub struct CpuLimiterApp {
     limiter: Arc<Limiter>,
-    system: System,
     last_update: Instant,
     filter_text: String,
     cached_processes: Vec<(i32, String, f32)>,
+    cached_groups: Vec<ProcessGroup>, // Busiest first
+    group_processes: bool,
     selected_pid: Option<i32>,
     limit_value: u32,
     is_active: bool,
     global_mode: bool,
     pub _tray_icon: Option<TrayIcon>,
-    quit_menu_id: MenuId,
+    tray_menu: TrayMenu,
+    tray_profiles: (Vec<String>, Option<String>), // Names and check mark last put in the tray
+    tray_controls: Option<(bool, LimiterMode, u32)>, // Active, mode and limit last shown; None forces an update
+    tray_paused: Vec<(i32, String)>,
+    tray_top: Vec<(i32, String, Submenu)>, // Entries of the top consumers submenu
+    tray_top_updated: Option<Instant>,
+    tray: TraySettings,
+    tray_title_text: String, // Edited templates, copied into `tray` once valid
+    tray_tooltip_text: String,
+    tray_texts: (String, String), // Title and tooltip last set
+    tray_icon_frame: Option<Vec<u8>>, // RGBA of the live icon last set; None while tray.png shows
+    menu_events: Receiver<MenuEvent>, // Forwarded so they wake the app while the window is hidden
     allow_close: bool,
+    instance_commands: Receiver<InstanceCommand>, // From the control socket
+    _control: Option<ControlServer>,
+    _http: Option<HttpServer>,
+    observed: (bool, LimiterMode, u32, Option<i32>), // Limiter settings last mirrored into the form
+    config_file: ConfigFile,
+    saved_config: Config, // What the file holds, as far as we know
+    config_error: Option<String>, // Saving pauses while the file is invalid
+    refresh: RefreshSettings,
+    profiles: Vec<Profile>,
+    profile_name: String, // Name for saving the current settings
+    audit: AuditSettings,
+    audit_entries: Vec<audit::Entry>, // Read when the viewer is opened or refreshed
+    audit_process: String,
+    audit_since: String,
+    audit_until: String,
+    export_result: Option<Result<PathBuf, String>>, // Last export from the history header
     
     // Visual & State Extras
     cpu_history: VecDeque<f64>,
@@ -33,6 +141,38 @@ pub struct CpuLimiterApp {
     start_time: Instant,
     cpu_count: usize,
     start_at_login: bool,
+
+    // Limiter settings
+    escalation_enabled: bool,
+    escalation: EscalationPolicy,
+    signal_text: String, // Name or number, applied once it parses
+    protected_text: String,
+    memory_rules: Vec<MemoryRule>,
+    target_names_text: String,
+    target_cgroups_text: String,
+    schedule_rows: Vec<ScheduleRow>,
+    power: PowerVariants,
+    thermal_enabled: bool,
+    thermal: ThermalTrigger,
+    thermal_zones: Vec<ThermalZone>,
+}
+
+/// Processes sharing a container or systemd unit, summed up.
+struct ProcessGroup {
+    group: Group,
+    cpu: f32,
+    count: usize,
+}
+
+/// Editable form of a schedule; `when` is parsed on every change.
+struct ScheduleRow {
+    name: String,
+    when: String,
+    global: bool,
+    limit: u32,
+    targets: String,
+    cgroups: Vec<String>, // Kept as loaded or as set when added; not edited here
+    error: Option<String>,
 }
 
 impl CpuLimiterApp {
@@ -40,84 +180,556 @@ impl CpuLimiterApp {
         cc: &eframe::CreationContext,
         limiter: Arc<Limiter>,
         tray_icon: Option<TrayIcon>,
-        quit_menu_id: MenuId,
+        tray_menu: TrayMenu,
     ) -> Self {
         configure_visuals(&cc.egui_ctx);
 
-        let mut system = System::new_all();
-        system.refresh_memory();
-        system.refresh_cpu_all();
-        let cpu_count = system.cpus().len();
         let start_at_login = Self::is_launch_agent_installed();
-        
-        Self {
+
+        let (command_sender, instance_commands) = mpsc::channel();
+        let on_command = {
+            let ctx = cc.egui_ctx.clone();
+            Arc::new(move |command| {
+                let _ = command_sender.send(command);
+                ctx.request_repaint();
+            })
+        };
+        {
+            // Keeps the tray live while the window is hidden and egui would not repaint
+            let ctx = cc.egui_ctx.clone();
+            limiter.on_sample(move || ctx.request_repaint());
+        }
+        let (menu_sender, menu_events) = mpsc::channel();
+        {
+            let ctx = cc.egui_ctx.clone();
+            MenuEvent::set_event_handler(Some(move |event| {
+                let _ = menu_sender.send(event);
+                ctx.request_repaint();
+            }));
+        }
+
+        let control = ControlServer::start(limiter.clone(), on_command)
+            .inspect_err(|e| log::warn!("control socket unavailable: {}", e))
+            .ok();
+        let http = match http::address_from_env() {
+            Ok(Some(addr)) => HttpServer::start(limiter.clone(), addr)
+                .inspect_err(|e| log::warn!("HTTP API unavailable: {:#}", e))
+                .ok(),
+            Ok(None) => None,
+            Err(e) => {
+                log::warn!("HTTP API disabled: {:#}", e);
+                None
+            }
+        };
+        let mut config_file = ConfigFile::new(config::default_path());
+        let (config, config_error) = match config_file.poll() {
+            Some(Ok(config)) => (config, None),
+            Some(Err(e)) => (Config::default(), Some(format!("{:#}", e))),
+            None => (Config::default(), None),
+        };
+        config.apply(&limiter);
+        let state = limiter.get_state();
+        let observed = (state.is_active, state.mode, state.limit_percentage, state.target_pid);
+
+        let mut app = Self {
             limiter,
-            memory_used: system.used_memory(),
-            memory_total: system.total_memory(),
-            system,
+            memory_used: 0,
+            memory_total: 0,
             last_update: Instant::now(),
             filter_text: String::new(),
             cached_processes: Vec::new(),
+            cached_groups: Vec::new(),
+            group_processes: false,
             selected_pid: None,
             limit_value: 50,
             is_active: false,
             global_mode: false,
             _tray_icon: tray_icon,
-            quit_menu_id,
+            tray_menu,
+            tray_profiles: (Vec::new(), None),
+            tray_controls: None,
+            tray_paused: Vec::new(),
+            tray_top: Vec::new(),
+            tray_top_updated: None,
+            tray: config.tray.clone(),
+            tray_title_text: config.tray.title.clone(),
+            tray_tooltip_text: config.tray.tooltip.clone(),
+            tray_texts: (String::new(), String::new()),
+            tray_icon_frame: None,
+            menu_events,
             allow_close: false,
+            instance_commands,
+            _control: control,
+            _http: http,
+            observed,
+            config_file,
+            saved_config: config.clone(),
+            config_error,
+            refresh: config.refresh.clone(),
+            profiles: Vec::new(),
+            profile_name: String::new(),
+            audit: config.audit.clone(),
+            audit_entries: Vec::new(),
+            audit_process: String::new(),
+            audit_since: String::new(),
+            audit_until: String::new(),
+            export_result: None,
             cpu_history: VecDeque::with_capacity(300),
             total_cpu_usage: 0.0,
             uptime_seconds: 0,
             start_time: Instant::now(),
-            cpu_count,
+            cpu_count: 0,
             start_at_login,
+            escalation_enabled: false,
+            escalation: EscalationPolicy::default(),
+            signal_text: EscalationPolicy::default().signal.as_str().to_string(),
+            protected_text: String::new(),
+            memory_rules: Vec::new(),
+            target_names_text: String::new(),
+            target_cgroups_text: String::new(),
+            schedule_rows: Vec::new(),
+            power: PowerVariants::default(),
+            thermal_enabled: false,
+            thermal: ThermalTrigger::default(),
+            thermal_zones: Vec::new(),
+        };
+        app.refresh_processes();
+        app.load_form(&config);
+        app.saved_config = app.current_config();
+        app.update_tray_profiles();
+        app.update_tray_controls();
+        app
+    }
+
+    /// Fills the form from a loaded config; the limiter must already have it applied.
+    fn load_form(&mut self, config: &Config) {
+        self.load_limiter_form(&config.limiter);
+        self.is_active = config.active;
+        self.profiles = config.profiles.clone();
+        self.refresh = config.refresh.clone();
+        self.audit = config.audit.clone();
+        self.tray = config.tray.clone();
+        self.tray_title_text = config.tray.title.clone();
+        self.tray_tooltip_text = config.tray.tooltip.clone();
+        self.filter_text = config.ui.filter.clone();
+        self.group_processes = config.ui.group;
+        // A saved pid is only meaningful while the same program still has it
+        if let Some(selected) = &config.ui.selected {
+            let running = self.cached_processes.iter().any(|(pid, name, _)| *pid == selected.pid && *name == selected.name);
+            if running {
+                self.selected_pid = Some(selected.pid);
+            }
+        }
+    }
+
+    fn load_limiter_form(&mut self, settings: &LimiterSettings) {
+        self.limit_value = settings.limit_percentage.clamp(1, 99);
+        self.global_mode = settings.mode == LimiterMode::Global;
+        self.target_names_text = settings.target_names.join(", ");
+        self.target_cgroups_text = settings.target_cgroups.join(", ");
+        self.protected_text = settings.protected.join(", ");
+        self.escalation_enabled = settings.escalation.is_some();
+        if let Some(policy) = &settings.escalation {
+            self.escalation = policy.clone();
+            self.signal_text = policy.signal.as_str().to_string();
+        }
+        self.memory_rules = settings.memory_rules.clone();
+        self.schedule_rows = settings
+            .schedules
+            .iter()
+            .map(|schedule| ScheduleRow {
+                name: schedule.name.clone(),
+                when: schedule.when.to_string(),
+                global: schedule.config.mode == LimiterMode::Global,
+                limit: schedule.config.limit_percentage,
+                targets: schedule.config.target_names.join(", "),
+                cgroups: schedule.config.target_cgroups.clone(),
+                error: None,
+            })
+            .collect();
+        self.power = settings.power;
+        self.thermal_enabled = settings.thermal.is_some();
+        if let Some(trigger) = &settings.thermal {
+            self.thermal = trigger.clone();
+        }
+    }
+
+    /// The profile the limiter currently matches, if any.
+    fn active_profile(&self) -> Option<&Profile> {
+        let current = LimiterSettings::from_state(&self.limiter.get_state());
+        self.profiles.iter().find(|profile| profile.limiter == current)
+    }
+
+    fn switch_profile(&mut self, name: &str) {
+        let Some(profile) = self.profiles.iter().find(|p| p.name == name).cloned() else {
+            return;
+        };
+        profile.switch(&self.limiter);
+        self.selected_pid = None;
+        self.load_limiter_form(&profile.limiter);
+        self.update_tray_profiles();
+    }
+
+    /// Rebuilds the tray submenu when profiles or the active one changed.
+    fn update_tray_profiles(&mut self) {
+        let names: Vec<String> = self.profiles.iter().map(|p| p.name.clone()).collect();
+        let active = self.active_profile().map(|p| p.name.clone());
+        if self.tray_profiles.0 == names && self.tray_profiles.1 == active {
+            return;
+        }
+
+        let submenu = &self.tray_menu.profiles;
+        while submenu.remove_at(0).is_some() {}
+        for name in &names {
+            let id = MenuId::new(format!("{}{}", PROFILE_MENU_PREFIX, name));
+            let item = CheckMenuItem::with_id(id, name, true, active.as_ref() == Some(name), None);
+            let _ = submenu.append(&item);
+        }
+        submenu.set_enabled(!names.is_empty());
+        self.tray_profiles = (names, active);
+    }
+
+    /// Mirrors the limiter into the tray's toggle, mode and preset items.
+    fn update_tray_controls(&mut self) {
+        let state = self.limiter.get_state();
+        let current = (state.is_active, state.mode, state.limit_percentage);
+        if self.tray_controls == Some(current) {
+            return;
+        }
+        let menu = &self.tray_menu;
+        menu.toggle.set_text(if state.is_active { "Stop limiter" } else { "Activate limiter" });
+        menu.targeted.set_checked(state.mode == LimiterMode::Targeted);
+        menu.global.set_checked(state.mode == LimiterMode::Global);
+        for (item, percent) in menu.presets.iter().zip(LIMIT_PRESETS) {
+            item.set_checked(state.limit_percentage == percent);
+        }
+        self.tray_controls = Some(current);
+    }
+
+    /// Rebuilds the paused-processes submenu when the set changed.
+    fn update_tray_paused(&mut self) {
+        let mut paused: Vec<(i32, String)> = self
+            .limiter
+            .get_status()
+            .currently_paused_pids
+            .into_iter()
+            .map(|pid| {
+                let name = self.cached_processes.iter().find(|(p, _, _)| *p == pid).map(|(_, name, _)| name.clone());
+                (pid, name.unwrap_or_default())
+            })
+            .collect();
+        paused.sort();
+        paused.dedup();
+        if paused == self.tray_paused {
+            return;
+        }
+
+        let submenu = &self.tray_menu.paused;
+        while submenu.remove_at(0).is_some() {}
+        for (pid, name) in &paused {
+            let id = MenuId::new(format!("{}{}", RESUME_MENU_PREFIX, pid));
+            let item = MenuItem::with_id(id, format!("Resume now: {} ({})", name, pid), true, None);
+            let _ = submenu.append(&item);
+        }
+        submenu.set_text(format!("Paused processes ({})", paused.len()));
+        submenu.set_enabled(!paused.is_empty());
+        self.tray_paused = paused;
+    }
+
+    /// Redraws the live icon and fills in the title and tooltip templates.
+    fn update_tray_indicator(&mut self) {
+        let Some(tray_icon) = &self._tray_icon else {
+            return;
+        };
+        let status = self.limiter.get_status();
+        let memory = if self.memory_total > 0 { self.memory_used as f32 / self.memory_total as f32 * 100.0 } else { 0.0 };
+        let profile = self.active_profile().map(|p| p.name.clone());
+        let values = indicator::Values {
+            cpu: self.total_cpu_usage,
+            memory,
+            paused: status.currently_paused_pids.len(),
+            profile: profile.as_deref(),
+        };
+        let texts = (indicator::format(&self.tray.title, &values), indicator::format(&self.tray.tooltip, &values));
+        if texts != self.tray_texts {
+            tray_icon.set_title(Some(&texts.0));
+            let _ = tray_icon.set_tooltip(Some(&texts.1));
+            self.tray_texts = texts;
+        }
+
+        if self.tray.dynamic_icon {
+            let skip = self.cpu_history.len().saturating_sub(indicator::SIZE as usize);
+            let history: Vec<f32> = self.cpu_history.iter().skip(skip).map(|cpu| *cpu as f32).collect();
+            let activity = Activity::new(self.limiter.get_state().is_active, &status);
+            let frame = indicator::render(&history, activity);
+            // Between samples the graph does not move, so most refreshes have nothing to draw
+            if self.tray_icon_frame.as_ref() != Some(&frame) {
+                // Colored, so not a template even on macOS
+                set_tray_icon(tray_icon, indicator::dynamic_icon(frame.clone()), false);
+                self.tray_icon_frame = Some(frame);
+            }
+        } else if self.tray_icon_frame.take().is_some() {
+            set_tray_icon(tray_icon, Some(indicator::static_icon()), true);
         }
     }
 
+    fn tray_settings(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(egui::RichText::new("🖥 Tray").color(egui::Color32::LIGHT_GRAY))
+            .id_salt("tray_settings")
+            .show(ui, |ui| {
+                ui.checkbox(&mut self.tray.dynamic_icon, "Live CPU graph icon");
+                ui.label(egui::RichText::new("Title and tooltip, with {cpu} {memory} {paused} {profile}").size(10.0).color(egui::Color32::from_white_alpha(150)));
+                let fields = [
+                    ("Title", &mut self.tray_title_text, &mut self.tray.title),
+                    ("Tooltip", &mut self.tray_tooltip_text, &mut self.tray.tooltip),
+                ];
+                for (label, text, template) in fields {
+                    ui.horizontal(|ui| {
+                        ui.label(label);
+                        ui.text_edit_singleline(text);
+                    });
+                    // Keeps the last valid template, so a half-typed one is never saved
+                    match indicator::check_template(text) {
+                        Ok(()) => *template = text.clone(),
+                        Err(e) => {
+                            ui.label(egui::RichText::new(format!("{:#}", e)).size(10.0).color(egui::Color32::from_rgb(239, 68, 68)));
+                        }
+                    }
+                }
+            });
+    }
+
+    /// Lists the busiest processes with their quick actions, every `TOP_MENU_INTERVAL`;
+    /// the submenu is only rebuilt when the processes in it change.
+    fn update_tray_top(&mut self) {
+        if self.tray_top_updated.is_some_and(|t| t.elapsed() < TOP_MENU_INTERVAL) {
+            return;
+        }
+        self.tray_top_updated = Some(Instant::now());
+        let myself = std::process::id() as i32;
+        let top: Vec<(i32, String, f32)> = self
+            .cached_processes
+            .iter()
+            .filter(|(pid, _, cpu)| *pid != myself && *cpu > 0.0)
+            .take(TOP_MENU_COUNT)
+            .cloned()
+            .collect();
+
+        let label = |(pid, name, cpu): &(i32, String, f32)| format!("{} ({}) — {:.0}%", name, pid, cpu);
+        // Same processes in the same order: only the usage figures move
+        if top.len() == self.tray_top.len()
+            && top.iter().zip(&self.tray_top).all(|((pid, name, _), (p, n, _))| pid == p && name == n)
+        {
+            for (entry, (_, _, menu)) in top.iter().zip(&self.tray_top) {
+                menu.set_text(label(entry));
+            }
+            return;
+        }
+
+        let submenu = &self.tray_menu.top;
+        while submenu.remove_at(0).is_some() {}
+        self.tray_top.clear();
+        for entry in top {
+            let menu = Submenu::new(label(&entry), true);
+            for action in QuickAction::ALL {
+                let _ = menu.append(&MenuItem::with_id(action.menu_id(entry.0), action.label(), true, None));
+            }
+            let _ = submenu.append(&menu);
+            self.tray_top.push((entry.0, entry.1, menu));
+        }
+        submenu.set_enabled(!self.tray_top.is_empty());
+    }
+
+    fn quick_action(&mut self, pid: i32, action: QuickAction) {
+        if action != QuickAction::Protect
+            && let Some(reason) = self.limiter.manual_refusal(pid)
+        {
+            return log::warn!("Leaving pid {} alone: {}", pid, reason);
+        }
+        match action {
+            QuickAction::Limit(percent) => {
+                self.limiter.update(|state| {
+                    state.mode = LimiterMode::Targeted;
+                    state.target_pid = Some(pid);
+                    state.limit_percentage = percent;
+                    state.is_active = true;
+                });
+            }
+            QuickAction::Pause => self.limiter.pause_now(pid),
+            QuickAction::Protect => {
+                let Some((_, name, _)) = self.tray_top.iter().find(|(p, _, _)| *p == pid) else {
+                    return;
+                };
+                let mut protected = split_names(&self.protected_text);
+                if !protected.iter().any(|p| p.eq_ignore_ascii_case(name)) {
+                    protected.push(name.clone());
+                    self.protected_text = protected.join(", ");
+                    self.limiter.set_protected(protected);
+                }
+            }
+        }
+    }
+
+    fn current_config(&self) -> Config {
+        let state = self.limiter.get_state();
+        let selected = self.selected_pid.and_then(|selected| {
+            self.cached_processes
+                .iter()
+                .find(|(pid, _, _)| *pid == selected)
+                .map(|(pid, name, _)| SelectedProcess { pid: *pid, name: name.clone() })
+        });
+        Config {
+            active: state.is_active,
+            limiter: LimiterSettings::from_state(&state),
+            profiles: self.profiles.clone(),
+            refresh: RefreshSettings {
+                check_interval_ms: state.check_interval.as_millis() as u64,
+                ..self.refresh.clone()
+            },
+            audit: self.audit.clone(),
+            tray: self.tray.clone(),
+            ui: UiSettings { filter: self.filter_text.clone(), selected, group: self.group_processes },
+        }
+    }
+
+    /// Picks up external edits of the config file, then saves our own changes.
+    fn sync_config(&mut self) {
+        match self.config_file.poll() {
+            Some(Ok(config)) => {
+                log::info!("reloaded {}", self.config_file.path().display());
+                config.apply(&self.limiter);
+                self.load_form(&config);
+                self.saved_config = self.current_config();
+                self.config_error = None;
+                return;
+            }
+            Some(Err(e)) => {
+                self.config_error = Some(format!("{:#}", e));
+                return;
+            }
+            None => {}
+        }
+        if self.config_error.is_none() {
+            self.save_config();
+        }
+    }
+
+    fn save_config(&mut self) {
+        let current = self.current_config();
+        if current != self.saved_config {
+            self.write_config(current);
+        }
+    }
+
+    fn write_config(&mut self, config: Config) {
+        match self.config_file.save(&config) {
+            Ok(()) => self.saved_config = config,
+            Err(e) => log::warn!("failed to save {}: {:#}", self.config_file.path().display(), e),
+        }
+    }
+
+    /// Writes the sampled history plus the audit log to the home directory.
+    fn export_history(&mut self, format: Format) {
+        let actions = audit::read(&audit::default_path(), self.audit.files);
+        let path = history::default_export_path(format);
+        let result = history::export(&self.limiter.history(), &actions, format)
+            .and_then(|text| Ok(std::fs::write(&path, text)?));
+        self.export_result = Some(result.map(|()| path).map_err(|e| format!("{:#}", e)));
+    }
+
     fn refresh_processes(&mut self) {
-        self.system.refresh_processes(sysinfo::ProcessesToUpdate::All, true);
-        self.system.refresh_cpu_all();
-        self.system.refresh_memory();
-        
-        self.total_cpu_usage = self.system.global_cpu_usage();
-        self.memory_used = self.system.used_memory();
-        self.memory_total = self.system.total_memory();
+        let snapshot = self.limiter.snapshot();
+        self.total_cpu_usage = snapshot.cpu_percent;
+        self.memory_used = snapshot.memory_used;
+        self.memory_total = snapshot.memory_total;
+        self.cpu_count = snapshot.per_core.len();
         self.uptime_seconds = self.start_time.elapsed().as_secs();
-        if let Some(tray_icon) = &self._tray_icon {
-            let percent = self.total_cpu_usage.round().clamp(0.0, 100.0) as u8;
-            let title = format!("{:>3}%", percent);
-            tray_icon.set_title(Some(title));
-        }
-        
         if self.cpu_history.len() >= 300 {
             self.cpu_history.pop_front();
         }
         self.cpu_history.push_back(self.total_cpu_usage as f64);
+        self.update_tray_indicator();
+        if !snapshot.processes.is_empty() {
+            self.limiter.record_sample(Sample::from_snapshot(&snapshot));
+        }
 
-        let mut procs: Vec<_> = self.system.processes().iter()
-            .map(|(pid, proc)| {
-                (
-                    pid.as_u32() as i32,
-                    proc.name().to_string_lossy().to_string(),
-                    proc.cpu_usage(),
-                )
-            })
+        self.cached_processes = snapshot.by_cpu().into_iter()
+            .map(|process| (process.pid, process.name.clone(), process.cpu_usage))
             .collect();
 
-        procs.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap_or(std::cmp::Ordering::Equal));
-
-        self.cached_processes = procs;
+        let mut groups: HashMap<Group, (f32, usize)> = HashMap::new();
+        for process in snapshot.processes.values() {
+            if let Some(group) = process.cgroup.as_deref().and_then(cgroup::group) {
+                let (cpu, count) = groups.entry(group).or_default();
+                *cpu += process.cpu_usage;
+                *count += 1;
+            }
+        }
+        self.cached_groups = groups.into_iter().map(|(group, (cpu, count))| ProcessGroup { group, cpu, count }).collect();
+        self.cached_groups.sort_by(|a, b| b.cpu.partial_cmp(&a.cpu).unwrap_or(std::cmp::Ordering::Equal).then_with(|| a.group.name.cmp(&b.group.name)));
     }
 
     fn handle_menu_events(&mut self, ctx: &egui::Context) {
-        let receiver = MenuEvent::receiver();
-        while let Ok(event) = receiver.try_recv() {
-            if event.id == self.quit_menu_id {
-                self.allow_close = true;
-                ctx.send_viewport_cmd(egui::ViewportCommand::Visible(true));
-                ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
-                ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+        while let Ok(event) = self.menu_events.try_recv() {
+            let id = event.id.as_ref();
+            if event.id == self.tray_menu.quit_id {
+                self.quit(ctx);
+            } else if event.id == *self.tray_menu.toggle.id() {
+                self.is_active = !self.limiter.get_state().is_active;
+                self.limiter.toggle(self.is_active);
+            } else if event.id == *self.tray_menu.targeted.id() {
+                self.limiter.set_mode(LimiterMode::Targeted);
+            } else if event.id == *self.tray_menu.global.id() {
+                self.limiter.set_mode(LimiterMode::Global);
+            } else if let Some(percent) = id.strip_prefix(LIMIT_MENU_PREFIX).and_then(|p| p.parse().ok()) {
+                self.limiter.set_limit(percent);
+            } else if let Some((pid, action)) = QuickAction::parse(id) {
+                self.quick_action(pid, action);
+            } else if let Some(pid) = id.strip_prefix(RESUME_MENU_PREFIX).and_then(|p| p.parse().ok()) {
+                self.limiter.resume_now(pid);
+            } else if let Some(name) = id.strip_prefix(PROFILE_MENU_PREFIX) {
+                self.switch_profile(name);
             }
+            // Check items toggle themselves on click; put back what the limiter says
+            self.tray_controls = None;
+        }
+        self.update_tray_controls();
+    }
+
+    fn handle_instance_commands(&mut self, ctx: &egui::Context) {
+        while let Ok(command) = self.instance_commands.try_recv() {
+            match command {
+                InstanceCommand::Stop => self.quit(ctx),
+                InstanceCommand::Activate(args) => {
+                    // A second launch: show the window unless it asked to stay in the tray
+                    if !matches!(cli::parse(args), Ok(Command::Gui { minimized: true, .. })) {
+                        self.show_window(ctx);
+                    }
+                }
+            }
+        }
+    }
+
+    fn quit(&mut self, ctx: &egui::Context) {
+        self.allow_close = true;
+        ctx.send_viewport_cmd(egui::ViewportCommand::Visible(true));
+        ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+        ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+    }
+
+    /// Mirrors changes made from outside the window (CLI, control socket) into the form.
+    fn sync_external_changes(&mut self) {
+        let state = self.limiter.get_state();
+        let current = (state.is_active, state.mode, state.limit_percentage, state.target_pid);
+        if current == self.observed {
+            return;
+        }
+        self.observed = current;
+        self.is_active = state.is_active;
+        self.global_mode = state.mode == LimiterMode::Global;
+        self.limit_value = state.limit_percentage.clamp(1, 99);
+        if state.target_pid.is_some() {
+            self.selected_pid = state.target_pid;
         }
     }
 
@@ -160,14 +772,29 @@ impl CpuLimiterApp {
 }
 
 impl eframe::App for CpuLimiterApp {
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        if self.config_error.is_none() {
+            self.save_config();
+        }
+        // Never leave processes stopped behind us
+        self.limiter.shutdown();
+    }
+
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        if self.last_update.elapsed() > Duration::from_millis(1000) {
+        if self.last_update.elapsed() > Duration::from_millis(self.refresh.process_list_ms) {
             self.refresh_processes();
+            self.sync_config();
+            self.update_tray_profiles();
+            self.update_tray_controls();
+            self.update_tray_paused();
+            self.update_tray_top();
             self.last_update = Instant::now();
         }
 
         self.handle_menu_events(ctx);
         self.handle_tray_events(ctx);
+        self.handle_instance_commands(ctx);
+        self.sync_external_changes();
 
         if ctx.input(|i| i.viewport().close_requested()) && !self.allow_close {
             ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
@@ -219,7 +846,11 @@ impl eframe::App for CpuLimiterApp {
                     });
                     
                     ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
-                        let status_text = if self.is_active { "● ACTIVE" } else { "○ STANDBY" };
+                        let status_text = match self.limiter.get_status().active_schedule {
+                            Some(schedule) if self.is_active => format!("● ACTIVE • {}", schedule),
+                            _ if self.is_active => "● ACTIVE".to_string(),
+                            _ => "○ STANDBY".to_string(),
+                        };
                         let status_color = if self.is_active { accent_green } else { egui::Color32::from_white_alpha(100) };
                         
                         egui::Frame::group(ui.style())
@@ -234,6 +865,26 @@ impl eframe::App for CpuLimiterApp {
                 });
                 ui.add_space(16.0);
                 
+                // === CONFIG ERROR ===
+                if let Some(error) = self.config_error.clone() {
+                    let error_color = egui::Color32::from_rgb(239, 68, 68);
+                    egui::Frame::group(ui.style())
+                        .fill(error_color.gamma_multiply(0.1))
+                        .stroke(egui::Stroke::new(1.0, error_color.gamma_multiply(0.6)))
+                        .corner_radius(8)
+                        .inner_margin(10.0)
+                        .show(ui, |ui| {
+                            ui.label(egui::RichText::new("⚠ Config file not loaded, changes are not saved").size(12.0).strong().color(error_color));
+                            ui.label(egui::RichText::new(error).size(10.0).color(egui::Color32::from_white_alpha(180)));
+                            ui.label(egui::RichText::new("Fix the file to reload it, or replace it with the settings shown here.").size(10.0).color(egui::Color32::from_white_alpha(150)));
+                            if ui.button("Overwrite with current settings").clicked() {
+                                self.config_error = None;
+                                self.write_config(self.current_config());
+                            }
+                        });
+                    ui.add_space(16.0);
+                }
+
                 // === STATS CARDS ROW ===
                 egui::ScrollArea::horizontal()
                     .id_salt("stats_cards")
@@ -244,7 +895,7 @@ impl eframe::App for CpuLimiterApp {
                             let card_spacing = 8.0;
                             let min_card_outer = 150.0;
                             let available = ui.available_width();
-                            let card_outer_width = ((available - (card_spacing * 3.0)) / 4.0).max(min_card_outer);
+                            let card_outer_width = ((available - (card_spacing * 4.0)) / 5.0).max(min_card_outer);
                             let card_inner_margin = 10.0;
                             let card_stroke = 1.0;
                             let card_inner_width = (card_outer_width - (card_inner_margin * 2.0) - (card_stroke * 2.0)).max(0.0);
@@ -260,6 +911,14 @@ impl eframe::App for CpuLimiterApp {
                             Self::stat_card(ui, card_inner_width, card_color, accent_purple, "RAM", &format!("{:.1}%", mem_percent), "💾");
                             ui.add_space(card_spacing);
 
+                            // Temperature Card
+                            let temperature = match self.limiter.get_status().temperature_c {
+                                Some(celsius) => format!("{:.0}°C", celsius),
+                                None => "—".to_string(),
+                            };
+                            Self::stat_card(ui, card_inner_width, card_color, egui::Color32::from_rgb(239, 68, 68), "TEMP", &temperature, "🌡");
+                            ui.add_space(card_spacing);
+
                             // Processes Card
                             Self::stat_card(ui, card_inner_width, card_color, accent_orange, "PROCS", &format!("{}", self.cached_processes.len()), "🔢");
                             ui.add_space(card_spacing);
@@ -275,8 +934,23 @@ impl eframe::App for CpuLimiterApp {
                     ui.label(egui::RichText::new("📈 CPU HISTORY").size(11.0).strong().color(egui::Color32::from_white_alpha(180)));
                     ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
                         ui.label(egui::RichText::new(&format!("Current: {:.1}%", self.total_cpu_usage)).size(11.0).color(accent_color));
+                        if ui.small_button("JSON").on_hover_text("Export history and limiter actions as JSON").clicked() {
+                            self.export_history(Format::Json);
+                        }
+                        if ui.small_button("CSV").on_hover_text("Export history and limiter actions as CSV").clicked() {
+                            self.export_history(Format::Csv);
+                        }
                     });
                 });
+                match &self.export_result {
+                    Some(Ok(path)) => {
+                        ui.label(egui::RichText::new(format!("Exported to {}", path.display())).size(10.0).color(accent_green));
+                    }
+                    Some(Err(e)) => {
+                        ui.label(egui::RichText::new(format!("Export failed: {}", e)).size(10.0).color(egui::Color32::from_rgb(239, 68, 68)));
+                    }
+                    None => {}
+                }
                 ui.add_space(4.0);
                 
                 egui::Frame::canvas(ui.style())
@@ -383,6 +1057,26 @@ impl eframe::App for CpuLimiterApp {
                             ui.add(checkbox);
                             ui.label(egui::RichText::new("🌐 Global Auto-Limit Mode").color(egui::Color32::LIGHT_GRAY));
                         }).response.on_hover_text("Limits system when AVERAGE CPU exceeds target");
+
+                        if !self.global_mode {
+                            ui.add_space(4.0);
+                            ui.label(egui::RichText::new("🎯 Also limit by name (comma-separated)").size(10.0).color(egui::Color32::from_white_alpha(150)));
+                            if ui.text_edit_singleline(&mut self.target_names_text).changed() {
+                                self.limiter.set_target_names(split_names(&self.target_names_text));
+                            }
+                            ui.label(egui::RichText::new("🐳 Also limit cgroups (comma-separated paths)").size(10.0).color(egui::Color32::from_white_alpha(150)))
+                                .on_hover_text("Every process in the cgroup or below it, e.g. /system.slice/nginx.service");
+                            if ui.text_edit_singleline(&mut self.target_cgroups_text).changed() {
+                                let mut targets = split_names(&self.target_cgroups_text);
+                                // Half-typed paths are left out here rather than warned about
+                                targets.retain(|target| cgroup::check_target(target).is_ok());
+                                self.limiter.set_target_cgroups(targets);
+                            }
+                            let targets = split_names(&self.target_cgroups_text);
+                            if let Some(e) = targets.iter().find_map(|target| cgroup::check_target(target).err()) {
+                                ui.label(egui::RichText::new(format!("{:#}", e)).size(10.0).color(egui::Color32::from_rgb(239, 68, 68)));
+                            }
+                        }
                         
                         // Info box explaining global mode
                         if self.global_mode {
@@ -406,8 +1100,10 @@ impl eframe::App for CpuLimiterApp {
                         
                         if self.global_mode {
                             self.limiter.set_global(self.limit_value);
-                        } else if let Some(pid) = self.selected_pid {
-                            self.limiter.set_target(pid);
+                        } else if let Some(pid) = self.selected_pid
+                            && let Err(e) = self.limiter.set_target(pid)
+                        {
+                            log::warn!("{}", e);
                         }
 
                         ui.add_space(16.0);
@@ -442,6 +1138,21 @@ impl eframe::App for CpuLimiterApp {
                             }
                             ui.label(egui::RichText::new("🚀 Start at Login").color(egui::Color32::LIGHT_GRAY));
                         }).response.on_hover_text("Automatically start CPU Limiter when you log in");
+
+                        ui.add_space(8.0);
+                        self.profile_settings(ui);
+                        self.escalation_settings(ui);
+                        self.memory_settings(ui);
+                        self.schedule_settings(ui);
+                        self.power_settings(ui);
+                        self.thermal_settings(ui);
+                        self.tray_settings(ui);
+
+                        ui.add_space(4.0);
+                        ui.label(egui::RichText::new("🛡 Never kill (comma-separated names)").size(10.0).color(egui::Color32::from_white_alpha(150)));
+                        if ui.text_edit_singleline(&mut self.protected_text).changed() {
+                            self.limiter.set_protected(split_names(&self.protected_text));
+                        }
                     });
 
                 ui.add_space(16.0);
@@ -488,6 +1199,15 @@ impl eframe::App for CpuLimiterApp {
                                     } else {
                                         ui.label(egui::RichText::new("Mode: Targeted Process").size(10.0).color(egui::Color32::from_white_alpha(150)));
                                     }
+                                    if let Some(variant) = &limiter_status.power_variant {
+                                        ui.label(egui::RichText::new(format!("🔋 Using {} limits", variant)).size(10.0).color(egui::Color32::from_white_alpha(150)));
+                                    }
+                                    if limiter_status.thermal_throttling {
+                                        ui.label(egui::RichText::new("🌡 Thermal throttling").size(10.0).color(accent_orange));
+                                    }
+                                    if limiter_status.memory_pressure {
+                                        ui.label(egui::RichText::new("💾 Memory watchdog engaged").size(10.0).color(accent_purple));
+                                    }
                                 });
                                 
                                 ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
@@ -573,8 +1293,31 @@ impl eframe::App for CpuLimiterApp {
                                     });
                                 }
                             }
+
+                            // Recent escalation steps
+                            if !limiter_status.escalations.is_empty() {
+                                ui.add_space(8.0);
+                                ui.separator();
+                                ui.add_space(4.0);
+                                ui.label(egui::RichText::new("🚨 Escalations").size(10.0).color(egui::Color32::from_white_alpha(150)));
+                                for record in limiter_status.escalations.iter().rev().take(3) {
+                                    let step_text = match record.step {
+                                        EscalationStep::Throttled => "throttled".to_string(),
+                                        EscalationStep::Released => "released".to_string(),
+                                        EscalationStep::Protected => "protected".to_string(),
+                                        EscalationStep::Signaled(signal) => format!("sent {}", signal),
+                                        EscalationStep::Killed => "killed".to_string(),
+                                    };
+                                    let ago = record.time.elapsed().map(|e| e.as_secs()).unwrap_or(0);
+                                    let mut line = format!("{} ({}) {} at {:.0}% • {}s ago", record.name, record.pid, step_text, record.cpu_usage, ago);
+                                    if let Some(error) = &record.error {
+                                        line.push_str(&format!(" • {}", error));
+                                    }
+                                    ui.label(egui::RichText::new(line).size(9.0).color(egui::Color32::from_white_alpha(130)));
+                                }
+                            }
                         });
-                    
+
                     ui.add_space(16.0);
                 }
 
@@ -586,6 +1329,8 @@ impl eframe::App for CpuLimiterApp {
                             .hint_text("🔍 Search...")
                             .min_size(egui::vec2(140.0, 10.0))
                         );
+                        ui.toggle_value(&mut self.group_processes, "▦ Group")
+                            .on_hover_text("Group by container or systemd unit");
                     });
                 });
                 ui.add_space(8.0);
@@ -606,6 +1351,10 @@ impl eframe::App for CpuLimiterApp {
                                     .scroll_bar_visibility(ScrollBarVisibility::AlwaysHidden)
                                     .auto_shrink([false; 2])
                                     .show(ui, |ui| {
+                                        if self.group_processes {
+                                            self.process_groups(ui, accent_color);
+                                            return;
+                                        }
                                         let filtered: Vec<_> = self.cached_processes.iter()
                                             .filter(|(_, name, _)| {
                                                 self.filter_text.is_empty() || name.to_lowercase().contains(&self.filter_text.to_lowercase())
@@ -650,7 +1399,7 @@ impl eframe::App for CpuLimiterApp {
                                                 ).sense(egui::Sense::click())).clicked() 
                                                 {
                                                     self.selected_pid = Some(*pid);
-                                                    if !self.global_mode { self.limiter.set_target(*pid); }
+                                                    if !self.global_mode && let Err(e) = self.limiter.set_target(*pid) { log::warn!("{}", e); }
                                                 }
                                                 
                                                 let display_name = if name.len() > 22 { format!("{}...", &name[0..20]) } else { name.clone() };
@@ -659,7 +1408,7 @@ impl eframe::App for CpuLimiterApp {
                                                 ).sense(egui::Sense::click())).on_hover_text(name).clicked() 
                                                 {
                                                     self.selected_pid = Some(*pid);
-                                                    if !self.global_mode { self.limiter.set_target(*pid); }
+                                                    if !self.global_mode && let Err(e) = self.limiter.set_target(*pid) { log::warn!("{}", e); }
                                                 }
 
                                                 // CPU with mini progress bar visual
@@ -697,7 +1446,7 @@ impl eframe::App for CpuLimiterApp {
                                                     .clicked()
                                                     {
                                                         self.selected_pid = Some(*pid);
-                                                        if !self.global_mode { self.limiter.set_target(*pid); }
+                                                        if !self.global_mode && let Err(e) = self.limiter.set_target(*pid) { log::warn!("{}", e); }
                                                     }
                                                 });
                                                 
@@ -711,6 +1460,10 @@ impl eframe::App for CpuLimiterApp {
                 );
 
                 ui.add_space(16.0);
+
+                // === AUDIT LOG ===
+                self.audit_viewer(ui, card_color);
+                ui.add_space(16.0);
                 
                 // === FOOTER ===
                 ui.horizontal(|ui| {
@@ -746,18 +1499,493 @@ impl CpuLimiterApp {
                 });
             });
     }
-    
+
+    /// The process list by container or unit; a group can be made a target as a whole.
+    fn process_groups(&mut self, ui: &mut egui::Ui, accent: egui::Color32) {
+        let filter = self.filter_text.to_lowercase();
+        let shown: Vec<&ProcessGroup> = self
+            .cached_groups
+            .iter()
+            .filter(|g| filter.is_empty() || g.group.name.to_lowercase().contains(&filter))
+            .collect();
+        if shown.is_empty() {
+            ui.vertical_centered(|ui| {
+                ui.add_space(30.0);
+                ui.label(egui::RichText::new("No containers or units").weak().size(14.0));
+                ui.add_space(30.0);
+            });
+            return;
+        }
+
+        let mut targets = split_names(&self.target_cgroups_text);
+        let mut changed = false;
+        egui::Grid::new("process_groups").striped(true).num_columns(4).spacing([20.0, 10.0]).show(ui, |ui| {
+            ui.label(egui::RichText::new("GROUP").size(10.0).strong().color(egui::Color32::GRAY));
+            ui.label(egui::RichText::new("PROCS").size(10.0).strong().color(egui::Color32::GRAY));
+            ui.label(egui::RichText::new("CPU").size(10.0).strong().color(egui::Color32::GRAY));
+            ui.label("");
+            ui.end_row();
+
+            for entry in shown {
+                let path = &entry.group.path;
+                let targeted = targets.contains(path);
+                let color = if targeted { accent } else { egui::Color32::LIGHT_GRAY };
+                let icon = match entry.group.kind {
+                    Kind::Container => "🐳",
+                    Kind::Unit => "⚙",
+                };
+                ui.label(egui::RichText::new(format!("{} {}", icon, entry.group.name)).color(color).size(11.0)).on_hover_text(path);
+                ui.label(egui::RichText::new(entry.count.to_string()).color(color).monospace().size(11.0));
+                ui.label(egui::RichText::new(format!("{:.1}%", entry.cpu)).color(color).strong().size(11.0));
+                let (label, hover) = if targeted { ("✓", "Stop limiting this group") } else { ("🎯", "Limit every process in this group") };
+                if ui.small_button(label).on_hover_text(hover).clicked() {
+                    if targeted {
+                        targets.retain(|t| t != path);
+                    } else {
+                        targets.push(path.clone());
+                    }
+                    changed = true;
+                }
+                ui.end_row();
+            }
+        });
+        if changed {
+            self.target_cgroups_text = targets.join(", ");
+            self.limiter.set_target_cgroups(targets);
+        }
+    }
+
+    fn audit_viewer(&mut self, ui: &mut egui::Ui, card_color: egui::Color32) {
+        const SHOWN: usize = 200;
+
+        egui::Frame::group(ui.style())
+            .fill(card_color)
+            .stroke(egui::Stroke::new(1.0, egui::Color32::from_white_alpha(10)))
+            .corner_radius(12)
+            .inner_margin(12.0)
+            .show(ui, |ui| {
+                let response = egui::CollapsingHeader::new(egui::RichText::new("📜 AUDIT LOG").size(11.0).strong().color(egui::Color32::from_white_alpha(180)))
+                    .id_salt("audit_viewer")
+                    .show(ui, |ui| {
+                        if !self.audit.enabled {
+                            ui.label(egui::RichText::new("Disabled in the config file ([audit] enabled)").size(10.0).color(egui::Color32::from_white_alpha(150)));
+                        }
+                        let mut refresh = false;
+                        ui.horizontal(|ui| {
+                            ui.add(egui::TextEdit::singleline(&mut self.audit_process).hint_text("Process or PID").desired_width(110.0));
+                            ui.add(egui::TextEdit::singleline(&mut self.audit_since).hint_text("From 14:00").desired_width(70.0));
+                            ui.add(egui::TextEdit::singleline(&mut self.audit_until).hint_text("To 14:05").desired_width(70.0));
+                            refresh = ui.small_button("🔄").on_hover_text("Reload the log").clicked();
+                        });
+
+                        // Times are "HH:MM" today or "YYYY-MM-DD HH:MM"
+                        let mut filter = audit::Filter { process: self.audit_process.clone(), ..Default::default() };
+                        let mut invalid = false;
+                        for (text, bound) in [(&self.audit_since, &mut filter.since), (&self.audit_until, &mut filter.until)] {
+                            if !text.trim().is_empty() {
+                                *bound = audit::parse_time(text);
+                                invalid |= bound.is_none();
+                            }
+                        }
+                        if invalid {
+                            ui.label(egui::RichText::new("Use HH:MM or YYYY-MM-DD HH:MM").size(10.0).color(egui::Color32::from_rgb(239, 68, 68)));
+                        }
+
+                        let matching: Vec<&audit::Entry> = self.audit_entries.iter().rev().filter(|e| filter.matches(e)).take(SHOWN).collect();
+                        if matching.is_empty() {
+                            ui.label(egui::RichText::new("No matching entries").size(10.0).color(egui::Color32::from_white_alpha(150)));
+                        }
+                        egui::ScrollArea::vertical().id_salt("audit_entries").max_height(200.0).show(ui, |ui| {
+                            for entry in matching {
+                                let mut line = format!(
+                                    "{} {} {} ({}) • {}",
+                                    entry.time.format("%m-%d %H:%M:%S"),
+                                    entry.signal.as_deref().unwrap_or(entry.action.as_str()),
+                                    entry.name,
+                                    entry.pid,
+                                    entry.trigger.describe(),
+                                );
+                                let color = match &entry.outcome {
+                                    audit::Outcome::Ok => egui::Color32::from_white_alpha(150),
+                                    audit::Outcome::Failed { errno } => {
+                                        line.push_str(&format!(" • {}", errno));
+                                        egui::Color32::from_rgb(239, 68, 68)
+                                    }
+                                };
+                                ui.label(egui::RichText::new(line).size(9.0).monospace().color(color));
+                            }
+                        });
+                        refresh
+                    });
+
+                if response.header_response.clicked() || response.body_returned == Some(true) {
+                    self.audit_entries = audit::read(&audit::default_path(), self.audit.files);
+                }
+            });
+    }
+
+    fn profile_settings(&mut self, ui: &mut egui::Ui) {
+        let active = self.active_profile().map(|p| p.name.clone());
+        let mut switch = None;
+        let mut remove = None;
+        egui::CollapsingHeader::new(egui::RichText::new("🎭 Profiles").color(egui::Color32::LIGHT_GRAY))
+            .id_salt("profile_settings")
+            .show(ui, |ui| {
+                ui.label(egui::RichText::new("Profiles target by name; the selected process is not saved").size(10.0).color(egui::Color32::from_white_alpha(150)));
+                for profile in &self.profiles {
+                    ui.horizontal(|ui| {
+                        let is_active = active.as_ref() == Some(&profile.name);
+                        if ui.selectable_label(is_active, &profile.name).clicked() {
+                            switch = Some(profile.name.clone());
+                        }
+                        if ui.small_button("✖").clicked() {
+                            remove = Some(profile.name.clone());
+                        }
+                    });
+                }
+                ui.horizontal(|ui| {
+                    ui.add(egui::TextEdit::singleline(&mut self.profile_name).hint_text("Name").desired_width(120.0));
+                    let name = self.profile_name.trim().to_string();
+                    let exists = self.profiles.iter().any(|p| p.name == name);
+                    let label = if exists { "💾 Replace with current" } else { "💾 Save current" };
+                    if ui.add_enabled(!name.is_empty(), egui::Button::new(label)).clicked() {
+                        let limiter = LimiterSettings::from_state(&self.limiter.get_state());
+                        match self.profiles.iter_mut().find(|p| p.name == name) {
+                            Some(profile) => profile.limiter = limiter,
+                            None => self.profiles.push(Profile { name, limiter }),
+                        }
+                        self.profile_name.clear();
+                    }
+                });
+            });
+
+        if let Some(name) = remove {
+            self.profiles.retain(|p| p.name != name);
+        }
+        if let Some(name) = switch {
+            self.switch_profile(&name);
+        }
+    }
+
+    fn escalation_settings(&mut self, ui: &mut egui::Ui) {
+        let mut changed = false;
+        egui::CollapsingHeader::new(egui::RichText::new("🚨 Escalation").color(egui::Color32::LIGHT_GRAY))
+            .id_salt("escalation_settings")
+            .show(ui, |ui| {
+                changed |= ui.checkbox(&mut self.escalation_enabled, "Throttle, then signal sustained offenders").changed();
+
+                egui::Grid::new("escalation_grid").num_columns(2).spacing([12.0, 6.0]).show(ui, |ui| {
+                    let policy = &mut self.escalation;
+
+                    ui.label("Above");
+                    changed |= ui.add(egui::DragValue::new(&mut policy.threshold_percent).range(10.0..=1600.0).suffix("%")).changed();
+                    ui.end_row();
+
+                    let mut sustain_min = policy.sustain_secs / 60;
+                    ui.label("For");
+                    changed |= ui.add(egui::DragValue::new(&mut sustain_min).range(1..=240).suffix(" min")).changed();
+                    policy.sustain_secs = sustain_min * 60;
+                    ui.end_row();
+
+                    ui.label("Throttle to");
+                    changed |= ui.add(egui::DragValue::new(&mut policy.throttle_percent).range(1..=99).suffix("%")).changed();
+                    ui.end_row();
+
+                    let mut escalate_min = policy.escalate_after_secs / 60;
+                    ui.label("Signal after");
+                    changed |= ui.add(egui::DragValue::new(&mut escalate_min).range(1..=240).suffix(" min")).changed();
+                    policy.escalate_after_secs = escalate_min * 60;
+                    ui.end_row();
+
+                    ui.label("Signal");
+                    ui.horizontal(|ui| {
+                        let response = ui.add(egui::TextEdit::singleline(&mut self.signal_text).desired_width(80.0))
+                            .on_hover_text("Name or number, e.g. SIGTERM, INT or 9");
+                        match limiter::parse_signal(&self.signal_text) {
+                            Some(signal) => {
+                                if response.changed() && signal != policy.signal {
+                                    policy.signal = signal;
+                                    changed = true;
+                                }
+                            }
+                            None => {
+                                ui.label(egui::RichText::new("Unknown signal").size(10.0).color(egui::Color32::from_rgb(239, 68, 68)));
+                            }
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.label("Grace period");
+                    changed |= ui.add(egui::DragValue::new(&mut policy.grace_secs).range(0..=600).suffix(" s")).changed();
+                    ui.end_row();
+                });
+            });
+
+        if changed {
+            let policy = self.escalation_enabled.then(|| self.escalation.clone());
+            self.limiter.set_escalation(policy);
+        }
+    }
+
+    fn schedule_settings(&mut self, ui: &mut egui::Ui) {
+        let mut changed = false;
+        let mut remove = None;
+        egui::CollapsingHeader::new(egui::RichText::new("🗓 Schedules").color(egui::Color32::LIGHT_GRAY))
+            .id_salt("schedule_settings")
+            .show(ui, |ui| {
+                ui.label(egui::RichText::new("First match wins, e.g. \"Mon-Fri 09:00-18:00\"").size(10.0).color(egui::Color32::from_white_alpha(150)));
+                for (index, row) in self.schedule_rows.iter_mut().enumerate() {
+                    egui::Frame::group(ui.style())
+                        .corner_radius(8)
+                        .inner_margin(8.0)
+                        .show(ui, |ui| {
+                            ui.horizontal(|ui| {
+                                changed |= ui.add(egui::TextEdit::singleline(&mut row.name).hint_text("Name").desired_width(90.0)).changed();
+                                changed |= ui.add(egui::TextEdit::singleline(&mut row.when).hint_text("Mon-Fri 09:00-18:00")).changed();
+                                if ui.small_button("✖").clicked() {
+                                    remove = Some(index);
+                                }
+                            });
+                            ui.horizontal(|ui| {
+                                changed |= ui.checkbox(&mut row.global, "🌐 Global").changed();
+                                changed |= ui.add(egui::Slider::new(&mut row.limit, 1..=100).suffix("%")).changed();
+                            });
+                            if !row.global {
+                                changed |= ui.add(egui::TextEdit::singleline(&mut row.targets).hint_text("Process names, comma-separated")).changed();
+                            }
+                            if let Some(error) = &row.error {
+                                ui.label(egui::RichText::new(error).size(10.0).color(egui::Color32::from_rgb(239, 68, 68)));
+                            }
+                        });
+                }
+                if ui.small_button("➕ Add schedule").clicked() {
+                    self.schedule_rows.push(ScheduleRow {
+                        name: format!("Schedule {}", self.schedule_rows.len() + 1),
+                        when: "Mon-Fri 09:00-18:00".to_string(),
+                        global: true,
+                        limit: self.limit_value,
+                        targets: String::new(),
+                        cgroups: self.limiter.get_state().target_cgroups,
+                        error: None,
+                    });
+                    changed = true;
+                }
+            });
+
+        if let Some(index) = remove {
+            self.schedule_rows.remove(index);
+            changed = true;
+        }
+        if changed {
+            let mut schedules = Vec::new();
+            for row in &mut self.schedule_rows {
+                match row.when.parse::<CalendarSpec>() {
+                    Ok(when) => {
+                        row.error = None;
+                        schedules.push(Schedule {
+                            name: row.name.clone(),
+                            when,
+                            config: LimiterConfig {
+                                mode: if row.global { LimiterMode::Global } else { LimiterMode::Targeted },
+                                limit_percentage: row.limit,
+                                target_pid: None,
+                                target_names: split_names(&row.targets),
+                                target_cgroups: row.cgroups.clone(),
+                                escalation: self.escalation_enabled.then(|| self.escalation.clone()),
+                                memory_rules: self.memory_rules.clone(),
+                                power: self.power,
+                                thermal: self.thermal_enabled.then(|| self.thermal.clone()),
+                            },
+                        });
+                    }
+                    Err(e) => row.error = Some(e.to_string()),
+                }
+            }
+            self.limiter.set_schedules(schedules);
+        }
+    }
+
+    fn thermal_settings(&mut self, ui: &mut egui::Ui) {
+        let mut changed = false;
+        let response = egui::CollapsingHeader::new(egui::RichText::new("🌡 Thermal").color(egui::Color32::LIGHT_GRAY))
+            .id_salt("thermal_settings")
+            .show(ui, |ui| {
+                changed |= ui.checkbox(&mut self.thermal_enabled, "Throttle top consumers when hot").changed();
+
+                let trigger = &mut self.thermal;
+                ui.horizontal(|ui| {
+                    ui.label("Above");
+                    changed |= ui.add(egui::DragValue::new(&mut trigger.threshold_c).range(30.0..=110.0).suffix("°C")).changed();
+                    ui.label("back off");
+                    changed |= ui.add(egui::DragValue::new(&mut trigger.hysteresis_c).range(1.0..=30.0).suffix("°C")).changed();
+                    ui.label("at");
+                    changed |= ui.add(egui::DragValue::new(&mut trigger.throttle_percent).range(1..=99).suffix("%")).changed();
+                });
+
+                if self.thermal_zones.is_empty() {
+                    ui.label(egui::RichText::new("No thermal zones found").size(10.0).color(egui::Color32::from_white_alpha(150)));
+                }
+                ui.label(egui::RichText::new("Zones (none selected = all)").size(10.0).color(egui::Color32::from_white_alpha(150)));
+                for zone in &self.thermal_zones {
+                    let mut selected = trigger.zones.contains(&zone.name);
+                    let temperature = zone.temperature_c.map(|c| format!(" • {:.0}°C", c)).unwrap_or_default();
+                    if ui.checkbox(&mut selected, format!("{} ({}){}", zone.name, zone.kind, temperature)).changed() {
+                        trigger.zones.retain(|z| z != &zone.name);
+                        if selected {
+                            trigger.zones.push(zone.name.clone());
+                        }
+                        changed = true;
+                    }
+                }
+            });
+
+        // List zones each time the section is toggled, so readings are fresh
+        if response.header_response.clicked() {
+            self.thermal_zones = thermal::list_zones(&self.limiter.get_state().thermal_root);
+        }
+        if changed {
+            self.limiter.set_thermal(self.thermal_enabled.then(|| self.thermal.clone()));
+        }
+    }
+
+    fn power_settings(&mut self, ui: &mut egui::Ui) {
+        let mut changed = false;
+        egui::CollapsingHeader::new(egui::RichText::new("🔋 Power").color(egui::Color32::LIGHT_GRAY))
+            .id_salt("power_settings")
+            .show(ui, |ui| {
+                let source = match self.limiter.get_status().power {
+                    Some(power) if power.on_battery => match power.battery_percent {
+                        Some(percent) => format!("On battery • {}%", percent),
+                        None => "On battery".to_string(),
+                    },
+                    Some(_) => "On AC".to_string(),
+                    None if self.power.is_empty() => "Power source is read once a variant is enabled".to_string(),
+                    None => "No battery detected".to_string(),
+                };
+                ui.label(egui::RichText::new(source).size(10.0).color(egui::Color32::from_white_alpha(150)));
+
+                let default_limit = self.limit_value;
+                for (label, variant) in [("On AC", &mut self.power.on_ac), ("On battery", &mut self.power.on_battery)] {
+                    ui.horizontal(|ui| {
+                        let mut enabled = variant.is_some();
+                        if ui.checkbox(&mut enabled, label).changed() {
+                            *variant = enabled.then_some(PowerVariant { mode: None, limit_percentage: default_limit });
+                            changed = true;
+                        }
+                        if let Some(variant) = variant {
+                            changed |= Self::power_variant_editor(ui, label, variant);
+                        }
+                    });
+                }
+
+                ui.horizontal(|ui| {
+                    let mut enabled = self.power.battery_saver.is_some();
+                    if ui.checkbox(&mut enabled, "Saver below").changed() {
+                        self.power.battery_saver = enabled.then_some(BatterySaver {
+                            below_percent: 20,
+                            variant: PowerVariant { mode: None, limit_percentage: 25 },
+                        });
+                        changed = true;
+                    }
+                    if let Some(saver) = &mut self.power.battery_saver {
+                        changed |= ui.add(egui::DragValue::new(&mut saver.below_percent).range(1..=99).suffix("%")).changed();
+                        changed |= Self::power_variant_editor(ui, "saver", &mut saver.variant);
+                    }
+                });
+            });
+
+        if changed {
+            self.limiter.set_power_variants(self.power);
+        }
+    }
+
+    fn power_variant_editor(ui: &mut egui::Ui, id: &str, variant: &mut PowerVariant) -> bool {
+        let mode_text = |mode: Option<LimiterMode>| match mode {
+            None => "Same mode",
+            Some(LimiterMode::Targeted) => "Targeted",
+            Some(LimiterMode::Global) => "Global",
+        };
+        let mut changed = false;
+        egui::ComboBox::from_id_salt(("power_mode", id))
+            .selected_text(mode_text(variant.mode))
+            .show_ui(ui, |ui| {
+                for mode in [None, Some(LimiterMode::Targeted), Some(LimiterMode::Global)] {
+                    changed |= ui.selectable_value(&mut variant.mode, mode, mode_text(mode)).changed();
+                }
+            });
+        changed |= ui.add(egui::DragValue::new(&mut variant.limit_percentage).range(1..=100).suffix("%")).changed();
+        changed
+    }
+
+    fn memory_settings(&mut self, ui: &mut egui::Ui) {
+        const MB: u64 = 1024 * 1024;
+
+        let mut changed = false;
+        let mut remove = None;
+        egui::CollapsingHeader::new(egui::RichText::new("💾 Memory Watchdog").color(egui::Color32::LIGHT_GRAY))
+            .id_salt("memory_settings")
+            .show(ui, |ui| {
+                for (index, rule) in self.memory_rules.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        let (mut is_rss, mut threshold_mb) = match rule.trigger {
+                            MemoryTrigger::ProcessRss { bytes } => (true, bytes / MB),
+                            MemoryTrigger::AvailableBelow { bytes } => (false, bytes / MB),
+                        };
+                        egui::ComboBox::from_id_salt(("memory_trigger", index))
+                            .selected_text(if is_rss { "Process RSS >" } else { "Available <" })
+                            .show_ui(ui, |ui| {
+                                changed |= ui.selectable_value(&mut is_rss, true, "Process RSS >").changed();
+                                changed |= ui.selectable_value(&mut is_rss, false, "Available <").changed();
+                            });
+                        changed |= ui.add(egui::DragValue::new(&mut threshold_mb).range(64..=1_048_576).speed(16).suffix(" MB")).changed();
+                        rule.trigger = if is_rss {
+                            MemoryTrigger::ProcessRss { bytes: threshold_mb * MB }
+                        } else {
+                            MemoryTrigger::AvailableBelow { bytes: threshold_mb * MB }
+                        };
+
+                        egui::ComboBox::from_id_salt(("memory_action", index))
+                            .selected_text(format!("{:?}", rule.action))
+                            .show_ui(ui, |ui| {
+                                for action in [MemoryAction::Pause, MemoryAction::Deprioritize, MemoryAction::Terminate] {
+                                    changed |= ui.selectable_value(&mut rule.action, action, format!("{:?}", action)).changed();
+                                }
+                            });
+                        if ui.small_button("✖").clicked() {
+                            remove = Some(index);
+                        }
+                    });
+                }
+                if ui.small_button("➕ Add rule").clicked() {
+                    self.memory_rules.push(MemoryRule {
+                        trigger: MemoryTrigger::AvailableBelow { bytes: 1024 * MB },
+                        action: MemoryAction::Pause,
+                    });
+                    changed = true;
+                }
+            });
+
+        if let Some(index) = remove {
+            self.memory_rules.remove(index);
+            changed = true;
+        }
+        if changed {
+            self.limiter.set_memory_rules(self.memory_rules.clone());
+        }
+    }
+
     #[cfg(target_os = "macos")]
     fn get_launch_agent_path() -> Option<std::path::PathBuf> {
         let home = std::env::var_os("HOME")?;
         Some(std::path::PathBuf::from(home).join("Library/LaunchAgents/com.alexkads.cpulimiter.plist"))
     }
     
-    #[cfg(not(target_os = "macos"))]
+    #[cfg(not(any(target_os = "macos", target_os = "linux")))]
     fn get_launch_agent_path() -> Option<std::path::PathBuf> {
         None
     }
     
+    #[cfg(not(target_os = "linux"))]
     fn is_launch_agent_installed() -> bool {
         if let Some(path) = Self::get_launch_agent_path() {
             path.exists()
@@ -765,6 +1993,12 @@ impl CpuLimiterApp {
             false
         }
     }
+
+    /// Either the autostart entry or the systemd user service (see `cpu_limiter autostart`).
+    #[cfg(target_os = "linux")]
+    fn is_launch_agent_installed() -> bool {
+        Autostart::from_env().installed().is_some()
+    }
     
     #[cfg(target_os = "macos")]
     fn install_launch_agent() {
@@ -807,9 +2041,17 @@ impl CpuLimiterApp {
         let _ = std::fs::write(&plist_path, plist);
     }
     
-    #[cfg(not(target_os = "macos"))]
+    #[cfg(target_os = "linux")]
     fn install_launch_agent() {
-        // Not implemented for non-macOS
+        let result = std::env::current_exe().and_then(|exe| Autostart::from_env().install(Method::Desktop, &exe));
+        if let Err(e) = result {
+            log::warn!("could not install the autostart entry: {}", e);
+        }
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "linux")))]
+    fn install_launch_agent() {
+        // Not implemented for this platform
     }
     
     #[cfg(target_os = "macos")]
@@ -819,13 +2061,38 @@ impl CpuLimiterApp {
         }
     }
     
-    #[cfg(not(target_os = "macos"))]
+    #[cfg(target_os = "linux")]
     fn remove_launch_agent() {
-        // Not implemented for non-macOS
+        if let Err(e) = Autostart::from_env().remove() {
+            log::warn!("could not remove the autostart files: {}", e);
+        }
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "linux")))]
+    fn remove_launch_agent() {
+        // Not implemented for this platform
     }
 
 }
 
+/// `set_icon_with_as_template` does nothing outside macOS.
+fn set_tray_icon(tray_icon: &TrayIcon, icon: Option<Icon>, template: bool) {
+    #[cfg(target_os = "macos")]
+    let _ = tray_icon.set_icon_with_as_template(icon, template);
+    #[cfg(not(target_os = "macos"))]
+    {
+        let _ = template;
+        let _ = tray_icon.set_icon(icon);
+    }
+}
+
+fn split_names(text: &str) -> Vec<String> {
+    text.split(',')
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
 fn configure_visuals(ctx: &egui::Context) {
     let mut visuals = egui::Visuals::dark();
     visuals.window_fill = egui::Color32::from_rgb(20, 21, 30);
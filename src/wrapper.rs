@@ -0,0 +1,107 @@
+use crate::cli::RunOptions;
+use crate::limiter::Limiter;
+use anyhow::Context;
+use nix::sys::resource::{UsageWho, getrusage};
+use nix::sys::signal::{Signal, kill};
+use nix::unistd::Pid;
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+use std::time::{Duration, Instant};
+use tokio::signal::unix::{SignalKind, signal};
+
+/// Spawns the command with inherited stdio, limits it until it exits and
+/// returns the exit code to pass on.
+pub fn run(options: RunOptions) -> anyhow::Result<i32> {
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+    let _guard = runtime.enter(); // The child's reaper registers with this runtime
+    let started = Instant::now();
+
+    let mut child = tokio::process::Command::new(&options.command[0])
+        .args(&options.command[1..])
+        .spawn()
+        .with_context(|| format!("failed to start '{}'", options.command[0]))?;
+    let pid = child.id().context("child exited before it could be limited")? as i32;
+
+    let limiter = Limiter::new();
+    limiter.configure_from_env();
+    limiter.set_target(pid).map_err(anyhow::Error::msg)?;
+    limiter.set_limit(options.percent);
+    limiter.set_include_children(options.tree);
+    limiter.toggle(true);
+    limiter.start_background_task();
+
+    let status = runtime.block_on(async {
+        let mut forwarded = Vec::new();
+        for kind in [
+            SignalKind::interrupt(),
+            SignalKind::terminate(),
+            SignalKind::hangup(),
+            SignalKind::quit(),
+            SignalKind::user_defined1(),
+            SignalKind::user_defined2(),
+        ] {
+            forwarded.push((Signal::try_from(kind.as_raw_value())?, signal(kind)?));
+        }
+
+        loop {
+            let received = next_signal(&mut forwarded);
+            tokio::select! {
+                status = child.wait() => return anyhow::Ok(status?),
+                signal = received => {
+                    // A stopped child only acts on it once the next period resumes it
+                    let _ = kill(Pid::from_raw(pid), signal);
+                }
+            }
+        }
+    })?;
+
+    limiter.shutdown();
+    print_summary(&options, status, started.elapsed(), limiter.get_status().throttled_wall_ms);
+
+    Ok(match (status.code(), status.signal()) {
+        (Some(code), _) => code,
+        (None, Some(signal)) => 128 + signal,
+        (None, None) => 1,
+    })
+}
+
+/// Resolves with whichever forwarded signal arrives first.
+async fn next_signal(forwarded: &mut [(Signal, tokio::signal::unix::Signal)]) -> Signal {
+    std::future::poll_fn(|cx| {
+        for (signal, stream) in forwarded.iter_mut() {
+            if stream.poll_recv(cx).is_ready() {
+                return std::task::Poll::Ready(*signal);
+            }
+        }
+        std::task::Poll::Pending
+    })
+    .await
+}
+
+fn print_summary(options: &RunOptions, status: ExitStatus, elapsed: Duration, throttled_wall_ms: u64) {
+    // Covers the child and every descendant that was waited for
+    let (user, system) = match getrusage(UsageWho::RUSAGE_CHILDREN) {
+        Ok(usage) => {
+            let seconds = |t: nix::sys::time::TimeVal| t.tv_sec() as f64 + t.tv_usec() as f64 / 1e6;
+            (seconds(usage.user_time()), seconds(usage.system_time()))
+        }
+        Err(_) => (0.0, 0.0),
+    };
+    let outcome = match (status.code(), status.signal()) {
+        (Some(code), _) => format!("exited with code {}", code),
+        (None, Some(signal)) => format!("killed by signal {}", signal),
+        (None, None) => "exited".to_string(),
+    };
+    eprintln!(
+        "cpu_limiter: '{}' {} after {:.1}s; CPU time {:.1}s (user {:.1}s, sys {:.1}s), throttled {:.1}s at {}%{}",
+        options.command.join(" "),
+        outcome,
+        elapsed.as_secs_f64(),
+        user + system,
+        user,
+        system,
+        throttled_wall_ms as f64 / 1000.0,
+        options.percent,
+        if options.tree { " (process tree)" } else { "" },
+    );
+}